@@ -79,7 +79,14 @@ int main(int argc, char* argv[]) {
     svglite_fontdb_t db = svglite_fontdb_create();
     svglite_fontdb_load_fonts_dir(db, "/mnt/c/Windows/Fonts");
     printf("db: %p, len: %lu\n", db, svglite_fontdb_len(db));
-    printf("svglite_render: %d\n", svglite_render(&target, svg, 0, 0, 0, db));
+    printf("svglite_render: %d\n", svglite_render(&target, svg, SVGLITE_FILL_RULE_AUTO, 0, 0, db));
+    vg_lite_rectangle_t rect = {.x = 100, .y = 20, .width = 64, .height = 48};
+    printf("svglite_render_rect: %d\n", svglite_render_rect(&target, svg, SVGLITE_FILL_RULE_AUTO, 0, 0, db,
+                                                           &rect, SVGLITE_FIT_CONTAIN, SVGLITE_ALIGN_X_MID_Y_MID, NULL));
+    vg_lite_matrix_t view = {{{4, 0, -240}, {0, 4, -240}, {0, 0, 1}}};
+    printf("svglite_render_view: %d\n", svglite_render_view(&target, svg, SVGLITE_FILL_RULE_AUTO, 0, 0, db,
+                                                           SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT,
+                                                           &view, SVGLITE_VIEW_TARGET));
     return 0;
 }
 
@@ -89,9 +96,22 @@ vg_lite_error_t vg_lite_draw(vg_lite_buffer_t *target,
                                  vg_lite_matrix_t *matrix,
                                  vg_lite_blend_t   blend,
                                  vg_lite_color_t   color) {return 0;}
+vg_lite_error_t vg_lite_set_stroke(vg_lite_path_t *path,
+                                   vg_lite_cap_style_t cap_style,
+                                   vg_lite_join_style_t join_style,
+                                   vg_lite_float_t line_width,
+                                   vg_lite_float_t miter_limit,
+                                   vg_lite_float_t *dash_pattern,
+                                   vg_lite_uint32_t pattern_count,
+                                   vg_lite_float_t dash_phase,
+                                   vg_lite_color_t color) {path->stroke_color = color; return 0;}
+vg_lite_error_t vg_lite_update_stroke(vg_lite_path_t *path) {return 0;}
+vg_lite_error_t vg_lite_set_path_type(vg_lite_path_t *path, vg_lite_path_type_t path_type) {path->path_type = path_type; return 0;}
+vg_lite_error_t vg_lite_clear_path(vg_lite_path_t *path) {return 0;}
 vg_lite_error_t vg_lite_update_grad(vg_lite_linear_gradient_t *grad) {return 0;}
 vg_lite_error_t vg_lite_finish(void) {return 0;}
 vg_lite_error_t vg_lite_init_grad(vg_lite_linear_gradient_t *grad) {return 0;}
+vg_lite_error_t vg_lite_clear_grad(vg_lite_linear_gradient_t *grad) {return 0;}
 vg_lite_error_t vg_lite_set_grad(vg_lite_linear_gradient_t *grad,
                                      uint32_t count,
                                      uint32_t *colors,
@@ -106,11 +126,61 @@ vg_lite_error_t vg_lite_draw_gradient(vg_lite_buffer_t *target,
                                           vg_lite_matrix_t *matrix,
                                           vg_lite_linear_gradient_t *grad,
                                           vg_lite_blend_t blend) {return 0;}
+vg_lite_error_t vg_lite_draw_pattern(vg_lite_buffer_t *target,
+                                     vg_lite_path_t *path,
+                                     vg_lite_fill_t fill_rule,
+                                     vg_lite_matrix_t *path_matrix,
+                                     vg_lite_buffer_t *pattern_image,
+                                     vg_lite_matrix_t *pattern_matrix,
+                                     vg_lite_blend_t blend,
+                                     vg_lite_pattern_mode_t pattern_mode,
+                                     vg_lite_color_t pattern_color,
+                                     vg_lite_filter_t filter) {return 0;}
+vg_lite_uint32_t vg_lite_query_feature(vg_lite_feature_t feature) {return getenv("FAKE_VGLITE_NO_FEATURE") == NULL;}
+vg_lite_error_t vg_lite_source_global_alpha(vg_lite_global_alpha_t alpha_mode, vg_lite_uint8_t alpha_value) {return 0;}
+vg_lite_error_t vg_lite_set_linear_grad(vg_lite_ext_linear_gradient_t *grad,
+                                        vg_lite_uint32_t count,
+                                        vg_lite_color_ramp_t *color_ramp,
+                                        vg_lite_linear_gradient_parameter_t grad_param,
+                                        vg_lite_gradient_spreadmode_t spread_mode,
+                                        vg_lite_uint8_t pre_mult) {return 0;}
+vg_lite_error_t vg_lite_update_linear_grad(vg_lite_ext_linear_gradient_t *grad) {return 0;}
+vg_lite_matrix_t * vg_lite_get_linear_grad_matrix(vg_lite_ext_linear_gradient_t *grad) {return &grad->matrix;}
+vg_lite_error_t vg_lite_draw_linear_grad(vg_lite_buffer_t *target,
+                                         vg_lite_path_t *path,
+                                         vg_lite_fill_t fill_rule,
+                                         vg_lite_matrix_t *path_matrix,
+                                         vg_lite_ext_linear_gradient_t *grad,
+                                         vg_lite_color_t paint_color,
+                                         vg_lite_blend_t blend,
+                                         vg_lite_filter_t filter) {return 0;}
+vg_lite_error_t vg_lite_clear_linear_grad(vg_lite_ext_linear_gradient_t *grad) {return 0;}
+vg_lite_error_t vg_lite_set_radial_grad(vg_lite_radial_gradient_t *grad,
+                                        vg_lite_uint32_t count,
+                                        vg_lite_color_ramp_t *color_ramp,
+                                        vg_lite_radial_gradient_parameter_t grad_param,
+                                        vg_lite_gradient_spreadmode_t spread_mode,
+                                        vg_lite_uint8_t pre_mult) {return 0;}
+vg_lite_error_t vg_lite_update_radial_grad(vg_lite_radial_gradient_t *grad) {return 0;}
+vg_lite_matrix_t * vg_lite_get_radial_grad_matrix(vg_lite_radial_gradient_t *grad) {return &grad->matrix;}
+vg_lite_error_t vg_lite_draw_radial_grad(vg_lite_buffer_t *target,
+                                         vg_lite_path_t *path,
+                                         vg_lite_fill_t fill_rule,
+                                         vg_lite_matrix_t *path_matrix,
+                                         vg_lite_radial_gradient_t *grad,
+                                         vg_lite_color_t paint_color,
+                                         vg_lite_blend_t blend,
+                                         vg_lite_filter_t filter) {return 0;}
+vg_lite_error_t vg_lite_clear_radial_grad(vg_lite_radial_gradient_t *grad) {return 0;}
 vg_lite_error_t vg_lite_free(vg_lite_buffer_t *buffer) {return 0;}
 vg_lite_error_t vg_lite_allocate(vg_lite_buffer_t *buffer) {
     switch (buffer->format) {
         case VG_LITE_RGBA8888: buffer->stride = buffer->width * 4; break;
+        case VG_LITE_INDEX_1: buffer->stride = (buffer->width + 7) / 8; break;
+        case VG_LITE_INDEX_2: buffer->stride = (buffer->width + 3) / 4; break;
+        case VG_LITE_INDEX_4: buffer->stride = (buffer->width + 1) / 2; break;
         case VG_LITE_INDEX_8:
+        case VG_LITE_A8:
         case VG_LITE_L8: buffer->stride = buffer->width; break;
         default: return VG_LITE_NOT_SUPPORT;
     }
@@ -140,3 +210,40 @@ vg_lite_error_t vg_lite_blit(vg_lite_buffer_t *target, vg_lite_buffer_t *source,
     i += 1;
     return VG_LITE_SUCCESS;
 }
+vg_lite_error_t vg_lite_set_scissor(vg_lite_int32_t x, vg_lite_int32_t y, vg_lite_int32_t right, vg_lite_int32_t bottom) {
+    printf("scissor %d,%d,%d,%d\n", x, y, right, bottom);
+    return 0;
+}
+vg_lite_error_t vg_lite_gaussian_filter(vg_lite_float_t w0, vg_lite_float_t w1, vg_lite_float_t w2) {return 0;}
+vg_lite_error_t vg_lite_enable_scissor(void) {return 0;}
+vg_lite_error_t vg_lite_disable_scissor(void) {return 0;}
+vg_lite_error_t vg_lite_create_masklayer(vg_lite_buffer_t *masklayer, vg_lite_uint32_t width, vg_lite_uint32_t height) {
+    masklayer->width = width;
+    masklayer->height = height;
+    masklayer->format = VG_LITE_A8;
+    printf("create masklayer %ux%u\n", width, height);
+    return vg_lite_allocate(masklayer);
+}
+vg_lite_error_t vg_lite_fill_masklayer(vg_lite_buffer_t *masklayer, vg_lite_rectangle_t *rect, vg_lite_uint8_t value) {return 0;}
+vg_lite_error_t vg_lite_blend_masklayer(vg_lite_buffer_t *dst, vg_lite_buffer_t *src, vg_lite_mask_operation_t operation, vg_lite_rectangle_t *rect) {return 0;}
+vg_lite_error_t vg_lite_render_masklayer(vg_lite_buffer_t *masklayer, vg_lite_mask_operation_t operation, vg_lite_path_t *path, vg_lite_fill_t fill_rule, vg_lite_color_t color, vg_lite_matrix_t *matrix) {return 0;}
+vg_lite_error_t vg_lite_set_masklayer(vg_lite_buffer_t *masklayer) {return 0;}
+vg_lite_error_t vg_lite_enable_masklayer(void) {return 0;}
+vg_lite_error_t vg_lite_disable_masklayer(void) {return 0;}
+vg_lite_error_t vg_lite_destroy_masklayer(vg_lite_buffer_t *masklayer) {
+    printf("destroy masklayer\n");
+    free(masklayer->memory);
+    return 0;
+}
+vg_lite_error_t vg_lite_set_CLUT(vg_lite_uint32_t count, vg_lite_uint32_t *colors) {
+    printf("CLUT %u:", count);
+    for (vg_lite_uint32_t i = 0; i < count; i++) {
+        printf(" %08x", colors[i]);
+    }
+    printf("\n");
+    return 0;
+}
+vg_lite_error_t vg_lite_set_pixel_matrix(vg_lite_pixel_matrix_t matrix, vg_lite_pixel_channel_enable_t *channel) {return 0;}
+vg_lite_error_t vg_lite_enable_color_transform(void) {return 0;}
+vg_lite_error_t vg_lite_disable_color_transform(void) {return 0;}
+vg_lite_error_t vg_lite_set_color_transform(vg_lite_color_transform_t *values) {return 0;}
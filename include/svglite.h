@@ -36,6 +36,39 @@ extern "C" {
 typedef void* svglite_svg_t;
 typedef void* svglite_fontdb_t;
 
+/* fill_rule of svglite_render() which keeps the fill-rule of each path,
+ * VG_LITE_FILL_EVEN_ODD or VG_LITE_FILL_NON_ZERO overrides them */
+#define SVGLITE_FILL_RULE_AUTO ((vg_lite_fill_t)0)
+
+/* how svglite_render_fit() maps the viewBox onto the target */
+typedef enum svglite_fit {
+    SVGLITE_FIT_DOCUMENT = 0, /* preserveAspectRatio of the document */
+    SVGLITE_FIT_CONTAIN  = 1, /* uniform scale, whole viewBox visible */
+    SVGLITE_FIT_COVER    = 2, /* uniform scale, target filled, overflow clipped */
+    SVGLITE_FIT_STRETCH  = 3, /* non-uniform scale to the target size */
+    SVGLITE_FIT_NONE     = 4, /* one user unit per pixel */
+} svglite_fit_t;
+
+/* where the viewBox is placed when it doesn't fill the target */
+typedef enum svglite_align {
+    SVGLITE_ALIGN_DOCUMENT    = 0, /* align of the document's preserveAspectRatio */
+    SVGLITE_ALIGN_X_MIN_Y_MIN = 1,
+    SVGLITE_ALIGN_X_MID_Y_MIN = 2,
+    SVGLITE_ALIGN_X_MAX_Y_MIN = 3,
+    SVGLITE_ALIGN_X_MIN_Y_MID = 4,
+    SVGLITE_ALIGN_X_MID_Y_MID = 5,
+    SVGLITE_ALIGN_X_MAX_Y_MID = 6,
+    SVGLITE_ALIGN_X_MIN_Y_MAX = 7,
+    SVGLITE_ALIGN_X_MID_Y_MAX = 8,
+    SVGLITE_ALIGN_X_MAX_Y_MAX = 9,
+} svglite_align_t;
+
+/* space of the view matrix of svglite_render_view() */
+typedef enum svglite_view_space {
+    SVGLITE_VIEW_TARGET  = 0, /* target pixels, applied after the viewBox mapping */
+    SVGLITE_VIEW_VIEWBOX = 1, /* viewBox user units, applied before the viewBox mapping */
+} svglite_view_space_t;
+
 const char* svglite_version(void);
 svglite_fontdb_t svglite_fontdb_create(void);
 void svglite_fontdb_free(svglite_fontdb_t db);
@@ -45,13 +78,53 @@ void svglite_fontdb_load_system_fonts(svglite_fontdb_t db);
 size_t svglite_fontdb_len(svglite_fontdb_t db);
 
 void svglite_free(svglite_svg_t svg);
+/* images with image-rendering="optimizeSpeed" are sampled nearest, the CSS
+ * keywords pixelated and crisp-edges aren't recognized and sample bilinear */
 svglite_svg_t svglite_svg_from_data(const unsigned char* data, size_t len);
+/* frame of animated GIF images drawn by the following renders of svg,
+ * frames past the last one wrap around, 0 by default */
+void svglite_set_gif_frame(svglite_svg_t svg, size_t frame);
+/* same as svglite_render_fit() with SVGLITE_FIT_DOCUMENT and SVGLITE_ALIGN_DOCUMENT */
 vg_lite_error_t svglite_render(vg_lite_buffer_t* target,
                                svglite_svg_t svg,
                                vg_lite_fill_t fill_rule,
                                vg_lite_blend_t blend,
                                vg_lite_quality_t quality,
                                const svglite_fontdb_t db);
+/* renders with the viewBox mapped onto the whole target by fit and align */
+vg_lite_error_t svglite_render_fit(vg_lite_buffer_t* target,
+                                   svglite_svg_t svg,
+                                   vg_lite_fill_t fill_rule,
+                                   vg_lite_blend_t blend,
+                                   vg_lite_quality_t quality,
+                                   const svglite_fontdb_t db,
+                                   svglite_fit_t fit,
+                                   svglite_align_t align);
+/* renders into rect of target only, the output is scissored to it.
+ * transform is applied after the fit with the origin at the top-left
+ * corner of rect, NULL for none */
+vg_lite_error_t svglite_render_rect(vg_lite_buffer_t* target,
+                                    svglite_svg_t svg,
+                                    vg_lite_fill_t fill_rule,
+                                    vg_lite_blend_t blend,
+                                    vg_lite_quality_t quality,
+                                    const svglite_fontdb_t db,
+                                    const vg_lite_rectangle_t* rect,
+                                    svglite_fit_t fit,
+                                    svglite_align_t align,
+                                    const vg_lite_matrix_t* transform);
+/* renders with a pan, zoom or rotation given by view, nodes that end up
+ * entirely outside of target are skipped */
+vg_lite_error_t svglite_render_view(vg_lite_buffer_t* target,
+                                    svglite_svg_t svg,
+                                    vg_lite_fill_t fill_rule,
+                                    vg_lite_blend_t blend,
+                                    vg_lite_quality_t quality,
+                                    const svglite_fontdb_t db,
+                                    svglite_fit_t fit,
+                                    svglite_align_t align,
+                                    const vg_lite_matrix_t* view,
+                                    svglite_view_space_t space);
 
 #ifdef __cplusplus
 }
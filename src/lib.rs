@@ -30,17 +30,20 @@
 include!("./vg_lite.rs");
 
 use std::{
-    f64::consts::PI, ffi::{c_void, CStr}, io::{Read, Result}, mem::transmute, os::raw::c_char, ptr::{null, null_mut}, slice, vec
+    cell::RefCell, collections::{hash_map::Entry, HashMap}, ffi::{c_void, CStr}, io::{Cursor, Read, Result},
+    mem::{transmute, zeroed}, os::raw::c_char, ptr::null_mut, slice, sync::Arc, vec
 };
 use png::{BitDepth, ColorType};
 use usvg::{
     self,
     Node,
     NodeKind::{Group, Path, Image, Text},
-    Paint::{Color, LinearGradient, RadialGradient, Pattern},
-    Transform, Visibility,
-    PathSegment::{*}, NodeExt, Stop, Units, Tree,
+    Paint::{self, Color, LinearGradient, RadialGradient, Pattern},
+    Transform, Visibility, ImageRendering, PathData, PathBbox, PaintOrder, LineCap, LineJoin, Opacity, BlendMode,
+    PathSegment::{*}, NodeExt, Stop, StopOffset, Units, Tree, BaseGradient, SpreadMethod,
     ImageKind::*,
+    utils::view_box_to_transform,
+    Align,
 };
 use usvg_text_layout::*;
 
@@ -53,10 +56,24 @@ extern "C" fn svglite_version() -> *const u8 {
 
 struct VGLiteConfig {
     target: *mut vg_lite_buffer,
-    fill_rule: vg_lite_fill_t,
-    /// Not used
+    /// Overrides `fill-rule` of paths
+    fill_rule: Option<vg_lite_fill_t>,
+    /// Blend of each draw, changed by `mix-blend-mode` of a group
     blend: vg_lite_blend_t,
-    quality: vg_lite_quality_t
+    quality: vg_lite_quality_t,
+    /// Active scissor in target pixels, left, top, right and bottom
+    scissor: Option<[i32; 4]>,
+    /// Active mask layer, null if none
+    mask: *mut vg_lite_buffer,
+    /// Opacity of groups above an image that is drawn without a layer
+    opacity: Opacity,
+    /// Frame of animated GIF images
+    gif_frame: usize,
+    /// Decoding state of the animated GIF images of the document
+    gifs: *const GifCache,
+    /// Whether nodes outside the visible pixels are skipped, off below a group
+    /// that is visible as a whole or that is filtered
+    cull: bool
 }
 
 struct VecReader<'a> {
@@ -92,25 +109,41 @@ impl<'a> Read for VecReader<'a> {
 #[repr(C)]
 #[derive(Clone)]
 pub struct svglite_svg {
-    svg: *mut Tree
+    svg: *mut Document
+}
+
+/// A parsed SVG and the options that stay with it between renders
+struct Document {
+    tree: Tree,
+    /// Frame of animated GIF images, wraps around the frame count
+    gif_frame: usize,
+    gifs: GifCache
 }
 
 #[no_mangle]
 extern "C" fn svglite_free(svg: svglite_svg) {
     if !svg.svg.is_null() {
-        unsafe {Box::from_raw(svg.svg)};
+        drop(unsafe {Box::from_raw(svg.svg)});
     }
 }
 
 #[no_mangle]
 extern "C" fn svglite_svg_from_data(data: *const u8, len: usize) -> svglite_svg {
     if let Ok(svg) = Tree::from_data(unsafe {slice::from_raw_parts(data, len)}, &usvg::Options::default()) {
-        svglite_svg { svg: Box::into_raw(Box::new(svg)) }
+        svglite_svg { svg: Box::into_raw(Box::new(Document { tree: svg, gif_frame: 0, gifs: GifCache::default() })) }
     } else {
         svglite_svg { svg: std::ptr::null_mut() }
     }
 }
 
+#[no_mangle]
+extern "C" fn svglite_set_gif_frame(svg: *mut Document, frame: usize) {
+    if !svg.is_null() {
+        let svg = unsafe {&mut*svg};
+        svg.gif_frame = frame;
+    }
+}
+
 #[no_mangle]
 extern "C" fn svglite_fontdb_create() -> *mut fontdb::Database {
     Box::into_raw(Box::new(fontdb::Database::new()))
@@ -118,7 +151,7 @@ extern "C" fn svglite_fontdb_create() -> *mut fontdb::Database {
 
 #[no_mangle]
 extern "C" fn svglite_fontdb_free(db: *mut fontdb::Database) {
-    unsafe { Box::from_raw(db) };
+    drop(unsafe { Box::from_raw(db) });
 }
 
 #[no_mangle]
@@ -151,14 +184,130 @@ extern "C" fn svglite_fontdb_len(db: *mut fontdb::Database) -> usize {
     db.len()
 }
 
+/// Largest side of the buffer a nested SVG image or a pattern tile is rendered into
+const MAX_IMAGE_SIZE: f64 = 4096.;
+
+// fit modes of svglite_render_fit(), see svglite.h
+const SVGLITE_FIT_DOCUMENT: u32 = 0;
+const SVGLITE_FIT_CONTAIN: u32 = 1;
+const SVGLITE_FIT_COVER: u32 = 2;
+const SVGLITE_FIT_STRETCH: u32 = 3;
+const SVGLITE_FIT_NONE: u32 = 4;
+
+// aligns of svglite_render_fit(), see svglite.h
+const SVGLITE_ALIGN_DOCUMENT: u32 = 0;
+const SVGLITE_ALIGN_X_MIN_Y_MIN: u32 = 1;
+const SVGLITE_ALIGN_X_MID_Y_MIN: u32 = 2;
+const SVGLITE_ALIGN_X_MAX_Y_MIN: u32 = 3;
+const SVGLITE_ALIGN_X_MIN_Y_MID: u32 = 4;
+const SVGLITE_ALIGN_X_MID_Y_MID: u32 = 5;
+const SVGLITE_ALIGN_X_MAX_Y_MID: u32 = 6;
+const SVGLITE_ALIGN_X_MIN_Y_MAX: u32 = 7;
+const SVGLITE_ALIGN_X_MID_Y_MAX: u32 = 8;
+const SVGLITE_ALIGN_X_MAX_Y_MAX: u32 = 9;
+
 #[no_mangle]
 extern "C" fn svglite_render(
     target: &mut vg_lite_buffer,
-    svg: *mut Tree,
+    svg: *mut Document,
     fill_rule: vg_lite_fill_t,
     blend: vg_lite_blend_t,
     quality: vg_lite_quality_t,
     db: *mut fontdb::Database
+) -> vg_lite_error {
+    svglite_render_fit(target, svg, fill_rule, blend, quality, db, SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT)
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+extern "C" fn svglite_render_fit(
+    target: &mut vg_lite_buffer,
+    svg: *mut Document,
+    fill_rule: vg_lite_fill_t,
+    blend: vg_lite_blend_t,
+    quality: vg_lite_quality_t,
+    db: *mut fontdb::Database,
+    fit: u32,
+    align: u32
+) -> vg_lite_error {
+    let viewbox_mat = fit_transform(unsafe { &(*svg).tree.view_box }, target.width as f64, target.height as f64, fit, align);
+    render(target, svg, fill_rule, blend, quality, db, &viewbox_mat, None)
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+extern "C" fn svglite_render_rect(
+    target: &mut vg_lite_buffer,
+    svg: *mut Document,
+    fill_rule: vg_lite_fill_t,
+    blend: vg_lite_blend_t,
+    quality: vg_lite_quality_t,
+    db: *mut fontdb::Database,
+    rect: &vg_lite_rectangle,
+    fit: u32,
+    align: u32,
+    transform: *const vg_lite_matrix
+) -> vg_lite_error {
+    let scissor = if let Some(scissor) = dest_scissor(rect, target.width, target.height) {
+        scissor
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+    let transform = if transform.is_null() {
+        None
+    } else {
+        Some(unsafe { (*transform).to_transform() })
+    };
+    let viewbox_mat = rect_transform(unsafe { &(*svg).tree.view_box }, rect, fit, align, transform.as_ref());
+    render(target, svg, fill_rule, blend, quality, db, &viewbox_mat, Some(scissor))
+}
+
+// space of the view matrix of svglite_render_view(), see svglite.h,
+// any other value is SVGLITE_VIEW_TARGET
+const SVGLITE_VIEW_VIEWBOX: u32 = 1;
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+extern "C" fn svglite_render_view(
+    target: &mut vg_lite_buffer,
+    svg: *mut Document,
+    fill_rule: vg_lite_fill_t,
+    blend: vg_lite_blend_t,
+    quality: vg_lite_quality_t,
+    db: *mut fontdb::Database,
+    fit: u32,
+    align: u32,
+    view: &vg_lite_matrix,
+    space: u32
+) -> vg_lite_error {
+    let fit_mat = fit_transform(unsafe { &(*svg).tree.view_box }, target.width as f64, target.height as f64, fit, align);
+    let viewbox_mat = view_transform(&fit_mat, &view.to_transform(), space);
+    render(target, svg, fill_rule, blend, quality, db, &viewbox_mat, None)
+}
+
+/// Combines the viewBox mapping with a view matrix given in `space`
+fn view_transform(fit_mat: &Transform, view: &Transform, space: u32) -> Transform {
+    let mut m;
+    if space == SVGLITE_VIEW_VIEWBOX {
+        m = *fit_mat;
+        m.append(view);
+    } else {
+        m = *view;
+        m.append(fit_mat);
+    }
+    m
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render(
+    target: &mut vg_lite_buffer,
+    svg: *mut Document,
+    fill_rule: vg_lite_fill_t,
+    blend: vg_lite_blend_t,
+    quality: vg_lite_quality_t,
+    db: *mut fontdb::Database,
+    viewbox_mat: &Transform,
+    scissor: Option<[i32; 4]>
 ) -> vg_lite_error {
     let svg = unsafe {&*svg};
     let db = if db.is_null() {
@@ -166,436 +315,2625 @@ extern "C" fn svglite_render(
     } else {
         Some(unsafe {&*db})
     };
-    let mut viewbox_mat = Transform::default();
-    viewbox_mat.scale(target.width as f64 / svg.view_box.rect.width(), target.height as f64 / svg.view_box.rect.height());
-    viewbox_mat.translate(-svg.view_box.rect.left(), -svg.view_box.rect.top());
-    let error = dfs(&svg.root, &viewbox_mat, &VGLiteConfig {
+    let fill_rule = fill_rule_override(fill_rule);
+    let error = set_clip_state(scissor, null_mut());
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    let error = dfs(&svg.tree.root, viewbox_mat, &VGLiteConfig {
         target,
         fill_rule,
         blend,
         quality,
+        scissor,
+        mask: null_mut(),
+        opacity: Opacity::ONE,
+        gif_frame: svg.gif_frame,
+        gifs: &svg.gifs,
+        cull: true
     }, db);
+    // leave the scissor disabled for the caller's own drawing
+    let restore = set_clip_state(None, null_mut());
     if error != vg_lite_error_VG_LITE_SUCCESS {
         // println!("error: dfs() failed with code {}", error);
         return error;
     }
+    if restore != vg_lite_error_VG_LITE_SUCCESS {
+        return restore;
+    }
 
     unsafe { vg_lite_finish() }
 }
 
-#[allow(unused)]
-fn right_normal(x: f64, y: f64, x0: f64, y0: f64) -> (f64, f64) {
-    let vector = (x - x0, y - y0);
-    // right rotate
-    let normal = (vector.1, -vector.0);
-    let length = (normal.0.powi(2) + normal.1.powi(2)).sqrt();
-    (normal.0 / length, normal.1 / length)
+/// Scissor of a destination rectangle clamped to the target, `None` if nothing is visible
+fn dest_scissor(rect: &vg_lite_rectangle, width: i32, height: i32) -> Option<[i32; 4]> {
+    let left = rect.x.max(0);
+    let top = rect.y.max(0);
+    let right = rect.x.saturating_add(rect.width).min(width);
+    let bottom = rect.y.saturating_add(rect.height).min(height);
+    if left >= right || top >= bottom {
+        None
+    } else {
+        Some([left, top, right, bottom])
+    }
 }
 
-fn dfs(node: &Node, mat: &Transform, config: &VGLiteConfig, db: Option<&fontdb::Database>) -> u32 {
-    let mut m = mat.clone();
-    m.append(&node.transform());
-    match node.borrow().to_owned() {
-        Group(_group) => {
-            for child in node.children() {
-                let e = dfs(&child, &m, config, db);
-                if e != vg_lite_error_VG_LITE_SUCCESS {
-                    return e
-                }
+/// Maps the viewBox into a destination rectangle, `transform` applies after
+/// the fit with the origin at the top-left corner of the rectangle.
+fn rect_transform(
+    view_box: &usvg::ViewBox,
+    rect: &vg_lite_rectangle,
+    fit: u32,
+    align: u32,
+    transform: Option<&Transform>
+) -> Transform {
+    let mut m = Transform::new_translate(rect.x as f64, rect.y as f64);
+    if let Some(transform) = transform {
+        m.append(transform);
+    }
+    m.append(&fit_transform(view_box, rect.width as f64, rect.height as f64, fit, align));
+    m
+}
+
+/// Maps the viewBox onto a `width` x `height` area.
+///
+/// `align` is one of `SVGLITE_ALIGN_*`, `SVGLITE_ALIGN_DOCUMENT` keeps the
+/// align of the document's `preserveAspectRatio`.
+fn fit_transform(view_box: &usvg::ViewBox, width: f64, height: f64, fit: u32, align: u32) -> Transform {
+    let rect = view_box.rect;
+    let align = match align {
+        SVGLITE_ALIGN_X_MIN_Y_MIN => Align::XMinYMin,
+        SVGLITE_ALIGN_X_MID_Y_MIN => Align::XMidYMin,
+        SVGLITE_ALIGN_X_MAX_Y_MIN => Align::XMaxYMin,
+        SVGLITE_ALIGN_X_MIN_Y_MID => Align::XMinYMid,
+        SVGLITE_ALIGN_X_MID_Y_MID => Align::XMidYMid,
+        SVGLITE_ALIGN_X_MAX_Y_MID => Align::XMaxYMid,
+        SVGLITE_ALIGN_X_MIN_Y_MAX => Align::XMinYMax,
+        SVGLITE_ALIGN_X_MID_Y_MAX => Align::XMidYMax,
+        SVGLITE_ALIGN_X_MAX_Y_MAX => Align::XMaxYMax,
+        _ => view_box.aspect.align
+    };
+    let sx = width / rect.width();
+    let sy = height / rect.height();
+    let (sx, sy) = match fit {
+        SVGLITE_FIT_CONTAIN => (sx.min(sy), sx.min(sy)),
+        SVGLITE_FIT_COVER => (sx.max(sy), sx.max(sy)),
+        SVGLITE_FIT_STRETCH => (sx, sy),
+        SVGLITE_FIT_NONE => (1., 1.),
+        _ => if view_box.aspect.align == Align::None {
+            (sx, sy)
+        } else if view_box.aspect.slice {
+            (sx.max(sy), sx.max(sy))
+        } else {
+            (sx.min(sy), sx.min(sy))
+        }
+    };
+    let (ax, ay) = match align {
+        Align::None | Align::XMinYMin => (0., 0.),
+        Align::XMidYMin => (0.5, 0.),
+        Align::XMaxYMin => (1., 0.),
+        Align::XMinYMid => (0., 0.5),
+        Align::XMidYMid => (0.5, 0.5),
+        Align::XMaxYMid => (1., 0.5),
+        Align::XMinYMax => (0., 1.),
+        Align::XMidYMax => (0.5, 1.),
+        Align::XMaxYMax => (1., 1.),
+    };
+    Transform::new(
+        sx, 0., 0., sy,
+        (width - rect.width() * sx) * ax - rect.x() * sx,
+        (height - rect.height() * sy) * ay - rect.y() * sy
+    )
+}
+
+fn dfs_children(node: &Node, mat: &Transform, config: &VGLiteConfig, db: Option<&fontdb::Database>) -> u32 {
+    for child in node.children() {
+        let e = dfs(&child, mat, config, db);
+        if e != vg_lite_error_VG_LITE_SUCCESS {
+            return e
+        }
+    }
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+fn draw_group(
+    node: &Node,
+    group: &usvg::Group,
+    m: &Transform,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> u32 {
+    if let Some(config) = direct_config(node, group, config) {
+        return dfs_children(node, m, &config, db);
+    }
+    let blend = hw_blend_mode(group.blend_mode);
+    // children overlap each other, render them into a layer and fade it as a whole
+    let (width, height) = unsafe { ((*config.target).width, (*config.target).height) };
+    let mut layer = vg_lite_buffer::default(width, height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let error = allocate_layer(&mut layer);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    // clip paths and masks apply once to the layer as it's composited, and
+    // to the filter result rather than its source
+    let mut error = dfs_children_offscreen(node, m, &mut layer, config, db);
+    if error == vg_lite_error_VG_LITE_SUCCESS && !group.filters.is_empty() {
+        error = apply_filters(&mut layer, node, group, m);
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = if let Some(blend) = blend {
+            composite_layer(&mut layer, group.opacity, blend, config)
+        } else {
+            composite_layer_cpu(&mut layer, group.opacity, group.blend_mode, config)
+        };
+    }
+    unsafe { vg_lite_free(&mut layer) };
+    error
+}
+
+/// Config to draw the children of a group straight onto the target, none if
+/// they need a layer
+fn direct_config(node: &Node, group: &usvg::Group, config: &VGLiteConfig) -> Option<VGLiteConfig> {
+    if let Some(config) = single_image_config(node, group, config) {
+        return Some(config);
+    }
+    if group.opacity != Opacity::ONE || !group.filters.is_empty() {
+        return None;
+    }
+    if group.blend_mode == BlendMode::Normal {
+        return Some(VGLiteConfig { ..*config });
+    }
+    // a single draw blends the same way as its layer
+    match (hw_blend_mode(group.blend_mode), is_single_draw(node)) {
+        (Some(blend), true) => Some(VGLiteConfig {
+            blend,
+            ..*config
+        }),
+        _ => None
+    }
+}
+
+/// Render into a buffer which isn't target sized, the clip states are in
+/// target pixels so they are turned off meanwhile
+fn dfs_offscreen(
+    node: &Node,
+    mat: &Transform,
+    target: &mut vg_lite_buffer,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> u32 {
+    offscreen(target, config, |config| dfs(node, mat, config, db))
+}
+
+/// Render the children of a group into its layer, the clip path and mask
+/// apply when the layer is composited instead
+fn dfs_children_offscreen(
+    node: &Node,
+    mat: &Transform,
+    target: &mut vg_lite_buffer,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> u32 {
+    offscreen(target, config, |config| dfs_children(node, mat, config, db))
+}
+
+fn offscreen(
+    target: &mut vg_lite_buffer,
+    config: &VGLiteConfig,
+    render: impl FnOnce(&VGLiteConfig) -> u32
+) -> u32 {
+    let error = set_clip_state(None, null_mut());
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    let error = render(&offscreen_config(target, config));
+    let restore = set_clip_state(config.scissor, config.mask);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        error
+    } else {
+        restore
+    }
+}
+
+/// Config of the draws into an offscreen buffer, without the clip states and
+/// the opacity of the target
+fn offscreen_config(target: &mut vg_lite_buffer, config: &VGLiteConfig) -> VGLiteConfig {
+    VGLiteConfig {
+        target,
+        scissor: None,
+        mask: null_mut(),
+        opacity: Opacity::ONE,
+        ..*config
+    }
+}
+
+/// Blend a target sized layer onto the target with a global alpha
+fn composite_layer(
+    layer: &mut vg_lite_buffer,
+    opacity: Opacity,
+    blend: vg_lite_blend_t,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    // layer must be completed before reading it
+    let mut error = unsafe { vg_lite_finish() };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe { vg_lite_source_global_alpha(vg_lite_global_alpha_VG_LITE_SCALED, opacity.to_u8()) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe {
+        vg_lite_blit(
+            config.target,
+            layer,
+            &mut vg_lite_matrix::from_transform(&Transform::default()),
+            blend,
+            0,
+            vg_lite_filter_VG_LITE_FILTER_POINT
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    } else {
+        // layer is freed by caller, wait for the blit
+        error = unsafe { vg_lite_finish() };
+    }
+    // global alpha is a global state, restore it for following draws
+    unsafe { vg_lite_source_global_alpha(vg_lite_global_alpha_VG_LITE_NORMAL, 0xff) };
+    error
+}
+
+/// Decode and draw an image, `mat` is the matrix of the image node
+fn draw_image(
+    image: &usvg::Image,
+    mat: &Transform,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    let mut m = *mat;
+    // size of the image in its own units, a nested SVG is rendered at device resolution
+    let mut size = None;
+    // allocate new buffer to do BLITs
+    let mut buffer;
+    let error = match &image.kind {
+        SVG(tree) => {
+            let (width, height) = (tree.size.width(), tree.size.height());
+            let mut place = m;
+            place.append(&image_transform(&image.view_box, width, height));
+            let (sx, sy) = place.get_scale();
+            buffer = vg_lite_buffer::default(
+                (width * sx).ceil().clamp(1., MAX_IMAGE_SIZE) as i32,
+                (height * sy).ceil().clamp(1., MAX_IMAGE_SIZE) as i32,
+                vg_lite_buffer_format_VG_LITE_RGBA8888
+            );
+            let error = allocate_layer(&mut buffer);
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
             }
-            vg_lite_error_VG_LITE_SUCCESS
+            size = Some((width, height));
+            let mut content_mat = Transform::new_scale(buffer.width as f64 / width, buffer.height as f64 / height);
+            content_mat.append(&fit_transform(&tree.view_box, width, height, SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT));
+            dfs_offscreen(&tree.root, &content_mat, &mut buffer, config, db)
         },
-        Path(path) => {
-            if path.visibility != Visibility::Visible || path.data.is_empty() {
-                return vg_lite_error_VG_LITE_SUCCESS;
+        JPEG(jpeg) => {
+            let jpeg = jpeg.as_ref();
+            let jpeg_reader = VecReader::new(jpeg);
+            let mut decoder = jpeg::Decoder::new(jpeg_reader);
+            let image_info;
+            if decoder.read_info().is_err() {
+                eprintln!("read info error at {}:{}", file!(), line!());
+                return vg_lite_error_VG_LITE_NOT_SUPPORT;
             }
-
-            if let Some(fill) = path.fill {
-                // build path
-                let mut path_data = Vec::new();
-                for seg in path.data.segments() {
-                    match seg {
-                        MoveTo { x, y } => {
-                            path_data.push(unsafe { transmute::<u32, f32>(VLC_OP_MOVE) });
-                            path_data.push(x as f32);
-                            path_data.push(y as f32);
-                        },
-                        LineTo { x, y } => {
-                            path_data.push(unsafe { transmute::<u32, f32>(VLC_OP_LINE) });
-                            path_data.push(x as f32);
-                            path_data.push(y as f32);
-                        },
-                        CurveTo { x1, y1, x2, y2, x, y } => {
-                            path_data.push(unsafe { transmute::<u32, f32>(VLC_OP_CUBIC) });
-                            path_data.push(x1 as f32);
-                            path_data.push(y1 as f32);
-                            path_data.push(x2 as f32);
-                            path_data.push(y2 as f32);
-                            path_data.push(x as f32);
-                            path_data.push(y as f32);
-                        },
-                        ClosePath => {
-                            path_data.push(unsafe { transmute::<u32, f32>(VLC_OP_CLOSE) });
+            if let Some(info) = decoder.info() {
+                image_info = info;
+                buffer = vg_lite_buffer::default(
+                    image_info.width as i32,
+                    image_info.height as i32,
+                    match info.pixel_format {
+                        jpeg::PixelFormat::L8 => vg_lite_buffer_format_VG_LITE_L8,
+                        jpeg::PixelFormat::L16 => vg_lite_buffer_format_VG_LITE_L8,
+                        jpeg::PixelFormat::CMYK32 => vg_lite_buffer_format_VG_LITE_RGBA8888,
+                        jpeg::PixelFormat::RGB24 => vg_lite_buffer_format_VG_LITE_BGRA8888,
+                    }
+                );
+            } else {
+                eprintln!("read info error at {}:{}", file!(), line!());
+                return vg_lite_error_VG_LITE_NOT_SUPPORT;
+            }
+            let error = unsafe {vg_lite_allocate(&mut buffer)};
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
+            }
+            let buffer_memory = unsafe {
+                slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.height * buffer.stride) as usize)
+            };
+            // errors fall through to the common free of the buffer
+            if let Ok(image) = decoder.decode() {
+                match image_info.pixel_format {
+                    jpeg::PixelFormat::L8 => {
+                        // memcpy
+                        buffer_memory.copy_from_slice(&image);
+                        vg_lite_error_VG_LITE_SUCCESS
+                    },
+                    jpeg::PixelFormat::L16 => {
+                        // TODO
+                        buffer_memory.fill(0);
+                        vg_lite_error_VG_LITE_SUCCESS
+                    },
+                    jpeg::PixelFormat::RGB24 => {
+                        // RGB24 to RGB32
+                        if image.len() * 4 != (buffer.height * buffer.stride) as usize * 3 {
+                            eprintln!("image size error at {}:{}", file!(), line!());
+                            vg_lite_error_VG_LITE_INVALID_ARGUMENT
+                        } else {
+                            convert_rgb24(&image, buffer_memory, buffer.format);
+                            vg_lite_error_VG_LITE_SUCCESS
                         }
+                    },
+                    jpeg::PixelFormat::CMYK32 => {
+                        // TODO
+                        buffer_memory.fill(0);
+                        vg_lite_error_VG_LITE_SUCCESS
                     }
                 }
-                path_data.push(unsafe { transmute::<u32, f32>(VLC_OP_END) });
-
-                let bbox = if let Some(bbox) = node.calculate_bbox() {
-                    bbox
-                } else {
-                    eprintln!("Warning: path can't read bounding box, ID: {}", path.id);
-                    return vg_lite_error_VG_LITE_SUCCESS;
-                };
-
-                let mut mr = vg_lite_matrix::from_transform(&m);
-                let mut p = vg_lite_path {
-                    bounding_box: [
-                        bbox.x() as f32,
-                        bbox.y() as f32,
-                        (bbox.x() + bbox.width()) as f32,
-                        (bbox.x() + bbox.width()) as f32
-                    ],
-                    quality: config.quality,
-                    format: vg_lite_format_VG_LITE_FP32,
-                    uploaded: unsafe { transmute::<[u32;8], vg_lite_hw_memory>([0;8]) },
-                    path_length: (path_data.len() * 4) as u32,
-                    path: path_data.as_mut_ptr() as *mut c_void,
-                    path_changed: 1,
-                    pdata_internal: 0,
-                    path_type: vg_lite_path_type_VG_LITE_DRAW_FILL_PATH,
-                    stroke: null_mut(),
-                    stroke_path: null_mut(),
-                    stroke_size: 0,
-                    stroke_color: 0,
-                    add_end: 0
-                };
-
-                match fill.paint {
-                    Color(color) => {
-                        let c = ((fill.opacity.to_u8() as u32) << 24) |
-                        ((color.red as u32) << 0) |
-                        ((color.green as u32) << 8) |
-                        ((color.blue as u32) << 16);
-
-                        let error = unsafe { vg_lite_draw(
-                            config.target,
-                            &mut p,
-                            config.fill_rule,
-                            &mut mr,
-                            config.blend,
-                            c
-                        ) };
+            } else {
+                eprintln!("image decode error at {}:{}", file!(), line!());
+                vg_lite_error_VG_LITE_NOT_SUPPORT
+            }
+        }
+        PNG(png) => {
+            let decoder = png::Decoder::new(VecReader::new(png.as_ref()));
+            if let Ok(mut reader) = decoder.read_info() {
+                let info = reader.info();
+                if info.bit_depth == BitDepth::Sixteen {
+                    eprintln!("image bit depth 16 error at {}:{}", file!(), line!());
+                    return vg_lite_error_VG_LITE_NOT_SUPPORT;
+                }
+                match info.color_type {
+                    ColorType::Grayscale => {
+                        if reader.output_buffer_size() > (info.width * info.height) as usize {
+                            // imposible
+                            eprintln!("image size error at {}:{}", file!(), line!());
+                            return vg_lite_error_VG_LITE_NOT_SUPPORT;
+                        }
+                        // copy
+                        buffer = vg_lite_buffer::default(
+                            info.width as i32,
+                            info.height as i32,
+                            vg_lite_buffer_format_VG_LITE_L8
+                        );
+                        let error = unsafe {vg_lite_allocate(&mut buffer)};
                         if error != vg_lite_error_VG_LITE_SUCCESS {
-                            eprintln!("Error at {}:{}", file!(), line!());
                             return error;
                         }
+                        let buffer_memory = unsafe {
+                            slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.height * buffer.stride) as usize)
+                        };
+                        if reader.next_frame(buffer_memory).is_err() {
+                            unsafe {vg_lite_free(&mut buffer)};
+                            eprintln!("image decode error at {}:{}", file!(), line!());
+                            return vg_lite_error_VG_LITE_NOT_SUPPORT;
+                        }
                     },
-                    LinearGradient(lg) => {
-                        if lg.base.stops.len() > 16 {
-                            println!("Error: linearGradient stops must not bigger than 16");
+                    ColorType::Indexed => {
+                        let (width, height) = (info.width as usize, info.height as usize);
+                        let bits = info.bit_depth as usize;
+                        let clut = clut_colors(
+                            info.palette.as_deref().unwrap_or(&[]),
+                            info.trns.as_deref(),
+                            1 << bits
+                        );
+                        let mut indices = vec![0; reader.output_buffer_size()];
+                        if reader.next_frame(&mut indices).is_err() {
+                            eprintln!("image decode error at {}:{}", file!(), line!());
                             return vg_lite_error_VG_LITE_NOT_SUPPORT;
                         }
-
-                        // build gradient
-                        let mut grad: vg_lite_linear_gradient = unsafe { transmute([0usize;54]) };
-                        let mut error = unsafe { vg_lite_init_grad(&mut grad) };
+                        let line = (width * bits).div_ceil(8);
+                        let hw_index = unsafe {
+                            vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_IM_INDEX_FORMAT)
+                        } != 0;
+                        buffer = vg_lite_buffer::default(
+                            width as i32,
+                            height as i32,
+                            if hw_index { index_format(bits) } else { vg_lite_buffer_format_VG_LITE_RGBA8888 }
+                        );
+                        // PNG packs the first pixel in the most significant bits, which
+                        // older hardware can't read, those get the pixels of a byte reversed
+                        let big_endian = hw_index && unsafe {
+                            vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_INDEX_ENDIAN)
+                        } != 0;
+                        if big_endian {
+                            buffer.index_endian = vg_lite_index_endian_VG_LITE_INDEX_BIG_ENDIAN;
+                        }
+                        let error = unsafe {vg_lite_allocate(&mut buffer)};
                         if error != vg_lite_error_VG_LITE_SUCCESS {
-                            eprintln!("Error at {}:{}", file!(), line!());
                             return error;
                         }
-
-                        let mut colors: Vec<vg_lite_color_t> = (&lg.base.stops).into_iter().map(|x| {
-                            x.get_u32()
-                        }).collect();
-
-                        // stop is 0 to 255, use matrix to scale
-                        let mut stops: Vec<u32> = (&lg.base.stops).into_iter().map(|x| {
-                            x.offset.to_u8() as u32
-                        }).collect();
-
-                        let mat;
-                        unsafe {
-                            vg_lite_set_grad(
-                                &mut grad,
-                                stops.len() as u32,
-                                colors.as_mut_ptr(),
-                                stops.as_mut_ptr()
-                            );
-                            vg_lite_update_grad(&mut grad);
-                            mat = &mut *vg_lite_get_grad_matrix(&mut grad);
-                        }
-
-                        // gradient transform
-                        let mut grad_mat = Transform::default();
-                        let (x1, y1, x2, y2) = (lg.x1, lg.y1, lg.x2, lg.y2);
-                        let angle = (y2 - y1).atan2(x2 - x1) * 180. / PI;
-                        grad_mat.prepend(&m);
-                        match lg.base.units {
-                            Units::ObjectBoundingBox => {
-                                grad_mat.rotate_at(angle, bbox.x(), bbox.y());
-                                let s = ((bbox.width() * (x2 - x1)).powi(2) + (bbox.height() * (y2 - y1)).powi(2)).sqrt() / 256.;
-                                grad_mat.scale(s, s);
-                            },
-                            Units::UserSpaceOnUse => {
-                                grad_mat.rotate_at(angle, x1, y1);
-                                grad_mat.translate(x1, y1);
-                                let s = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt() / 256.;
-                                grad_mat.scale(s, s);
+                        for (y, src) in indices.chunks_exact(line).take(height).enumerate() {
+                            let row_size = if hw_index { line } else { width * 4 };
+                            let dst = unsafe {
+                                slice::from_raw_parts_mut(
+                                    (buffer.memory as *mut u8).add(y * buffer.stride as usize),
+                                    row_size
+                                )
+                            };
+                            if big_endian {
+                                dst.copy_from_slice(src);
+                            } else if hw_index {
+                                reverse_indices(src, dst, bits);
+                            } else {
+                                // no index formats, look the colors up here
+                                for (x, pixel) in dst.chunks_exact_mut(4).enumerate() {
+                                    let color = decode_pixel(clut[png_index(src, bits, x) as usize], TABLE_FORMAT);
+                                    pixel.copy_from_slice(&encode_pixel(color, buffer.format).to_le_bytes());
+                                }
                             }
-                        };
-
-                        grad_mat.append(&lg.transform);
-                        mat.update_transform(&grad_mat);
-                        unsafe {
-                            error = vg_lite_draw_grad(
-                                config.target,
-                                &mut p,
-                                vg_lite_fill_VG_LITE_FILL_EVEN_ODD,
-                                &mut mr,
-                                &mut grad,
-                                config.blend
-                            );
+                        }
+                        if hw_index {
+                            let mut clut = clut;
+                            let error = unsafe { vg_lite_set_CLUT(clut.len() as u32, clut.as_mut_ptr()) };
                             if error != vg_lite_error_VG_LITE_SUCCESS {
-                                eprintln!("Error {}:{}", file!(), line!());
+                                eprintln!("Error at {}:{}", file!(), line!());
+                                unsafe { vg_lite_free(&mut buffer) };
                                 return error;
                             }
-                        };
-                    },
-                    RadialGradient(_rg) => {
-                        // TODO
+                        }
                     },
-                    Pattern(_p) => {
+                    ColorType::GrayscaleAlpha => {
                         // TODO
-                    },
-                }
-            }
-            if let Some(_stroke) = path.stroke {
-                // stroke is not supported
-            }
-            vg_lite_error_VG_LITE_SUCCESS
-        },
-        Image(image) => {
-            if image.visibility != Visibility::Visible {
-                return vg_lite_error_VG_LITE_SUCCESS;
-            }
-            m.translate(image.view_box.rect.x(), image.view_box.rect.y());
-            // allocate new buffer to do BLITs
-            let mut buffer;
-            let error = match image.kind {
-                SVG(tree) => {
-                    buffer = vg_lite_buffer::default(
-                        image.view_box.rect.width() as i32,
-                        image.view_box.rect.height() as i32,
-                        vg_lite_buffer_format_VG_LITE_RGBA8888
-                    );
-                    let error = unsafe {vg_lite_allocate(&mut buffer)};
-                    if error != vg_lite_error_VG_LITE_SUCCESS {
-                        return error;
-                    }
-                    dfs(&tree.root, &Transform::default(), &VGLiteConfig {
-                        target: &mut buffer,
-                        fill_rule: config.fill_rule,
-                        blend: config.blend,
-                        quality: config.quality
-                    }, db)
-                },
-                JPEG(jpeg) => {
-                    let jpeg = jpeg.as_ref();
-                    let jpeg_reader = VecReader::new(jpeg);
-                    let mut decoder = jpeg::Decoder::new(jpeg_reader);
-                    let image_info;
-                    if let Err(_) = decoder.read_info() {
-                        eprintln!("read info error at {}:{}", file!(), line!());
-                        return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                    }
-                    if let Some(info) = decoder.info() {
-                        image_info = info;
-                        buffer = vg_lite_buffer::default(
-                            image_info.width as i32,
-                            image_info.height as i32,
-                            match info.pixel_format {
-                                jpeg::PixelFormat::L8 => vg_lite_buffer_format_VG_LITE_L8,
-                                jpeg::PixelFormat::L16 => vg_lite_buffer_format_VG_LITE_L8,
-                                jpeg::PixelFormat::CMYK32 => vg_lite_buffer_format_VG_LITE_RGBA8888,
-                                jpeg::PixelFormat::RGB24 => vg_lite_buffer_format_VG_LITE_BGRA8888,
-                            }
-                        );
-                    } else {
-                        eprintln!("read info error at {}:{}", file!(), line!());
+                        eprintln!("image format error at {}:{}", file!(), line!());
                         return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                    }
-                    let error = unsafe {vg_lite_allocate(&mut buffer)};
-                    if error != vg_lite_error_VG_LITE_SUCCESS {
-                        return error;
-                    }
-                    let buffer_memory = unsafe {
-                        slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.height * buffer.stride) as usize)
-                    };
-                    if let Ok(image) = decoder.decode() {
-                        match image_info.pixel_format {
-                            jpeg::PixelFormat::L8 => {
-                                // memcpy
-                                buffer_memory.copy_from_slice(&image);
-                            },
-                            jpeg::PixelFormat::L16 => {
-                                // TODO
-                                buffer_memory.fill(0);
-                            },
-                            jpeg::PixelFormat::RGB24 => {
-                                // RGB24 to RGB32
-                                if image.len() * 4 != (buffer.height * buffer.stride) as usize * 3 {
-                                    return vg_lite_error_VG_LITE_INVALID_ARGUMENT;
-                                }
-                                convert_rgb24_to_rgb32(&image, buffer_memory);
-                            },
-                            jpeg::PixelFormat::CMYK32 => {
-                                // TODO
-                                buffer_memory.fill(0);
+                    },
+                    ColorType::Rgb => {
+                        let mut rgb24_buffer = vec![0;reader.output_buffer_size()];
+                        if let Ok(output_info) = reader.next_frame(&mut rgb24_buffer) {
+                            // convert rgb24 to rgb32
+                            buffer = vg_lite_buffer::default(
+                                output_info.width as i32,
+                                output_info.height as i32,
+                                vg_lite_buffer_format_VG_LITE_RGBA8888
+                            );
+                            let error = unsafe {vg_lite_allocate(&mut buffer)};
+                            if error != vg_lite_error_VG_LITE_SUCCESS {
+                                return error;
                             }
+                            let buffer_memory = unsafe {
+                                slice::from_raw_parts_mut(
+                                    buffer.memory as *mut u8,
+                                    (buffer.height * buffer.stride) as usize
+                                )
+                            };
+                            convert_rgb24(&rgb24_buffer, buffer_memory, buffer.format);
+                        } else {
+                            eprintln!("read output info error at {}:{}", file!(), line!());
+                            return vg_lite_error_VG_LITE_NOT_SUPPORT;
                         }
-                    } else {
-                        eprintln!("image decode error at {}:{}", file!(), line!());
-                        return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                    }
-                    m.scale(
-                        image.view_box.rect.width() / image_info.width as f64,
-                        image.view_box.rect.height() / image_info.height as f64
-                    );
-                    vg_lite_error_VG_LITE_SUCCESS
-                }
-                PNG(png) => {
-                    let decoder = png::Decoder::new(VecReader::new(png.as_ref()));
-                    if let Ok(mut reader) = decoder.read_info() {
-                        let info = reader.info();
-                        if info.bit_depth == BitDepth::Sixteen {
-                            eprintln!("image bit depth 16 error at {}:{}", file!(), line!());
+                    },
+                    ColorType::Rgba => {
+                        if reader.output_buffer_size() > (info.width * info.height * 4) as usize {
+                            // imposible
+                            eprintln!("image size error at {}:{}", file!(), line!());
                             return vg_lite_error_VG_LITE_NOT_SUPPORT;
                         }
-                        m.scale(
-                            image.view_box.rect.width() / info.width as f64,
-                            image.view_box.rect.width() / info.height as f64
+                        // copy
+                        buffer = vg_lite_buffer::default(
+                            info.width as i32,
+                            info.height as i32,
+                            vg_lite_buffer_format_VG_LITE_RGBA8888
                         );
-                        match info.color_type {
-                            ColorType::Grayscale => {
-                                if reader.output_buffer_size() > (info.width * info.height) as usize {
-                                    // imposible
-                                    eprintln!("image size error at {}:{}", file!(), line!());
-                                    return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                                }
-                                // copy
-                                buffer = vg_lite_buffer::default(
-                                    info.width as i32,
-                                    info.height as i32,
-                                    vg_lite_buffer_format_VG_LITE_L8
-                                );
-                                let error = unsafe {vg_lite_allocate(&mut buffer)};
-                                if error != vg_lite_error_VG_LITE_SUCCESS {
-                                    return error;
-                                }
-                                let buffer_memory = unsafe {
-                                    slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.height * buffer.stride) as usize)
-                                };
-                                if let Err(_) = reader.next_frame(buffer_memory) {
-                                    unsafe {vg_lite_free(&mut buffer)};
-                                    eprintln!("image decode error at {}:{}", file!(), line!());
-                                    return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                                }
-                            },
-                            ColorType::Indexed => {
-                                // TODO: CLUT
-                                eprintln!("image format error at {}:{}", file!(), line!());
-                                return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                            },
-                            ColorType::GrayscaleAlpha => {
-                                // TODO
-                                eprintln!("image format error at {}:{}", file!(), line!());
-                                return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                            },
-                            ColorType::Rgb => {
-                                let mut rgb24_buffer = vec![0;reader.output_buffer_size()];
-                                if let Ok(output_info) = reader.next_frame(&mut rgb24_buffer) {
-                                    // convert rgb24 to rgb32
-                                    buffer = vg_lite_buffer::default(
-                                        output_info.width as i32,
-                                        output_info.height as i32,
-                                        vg_lite_buffer_format_VG_LITE_RGBA8888
-                                    );
-                                    let error = unsafe {vg_lite_allocate(&mut buffer)};
-                                    if error != vg_lite_error_VG_LITE_SUCCESS {
-                                        return error;
-                                    }
-                                    let buffer_memory = unsafe {
-                                        slice::from_raw_parts_mut(
-                                            buffer.memory as *mut u8,
-                                            (buffer.height * buffer.stride) as usize
-                                        )
-                                    };
-                                    convert_rgb24_to_rgb32(&rgb24_buffer, buffer_memory);
-                                } else {
-                                    eprintln!("read output info error at {}:{}", file!(), line!());
-                                    return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                                }
-                            },
-                            ColorType::Rgba => {
-                                if reader.output_buffer_size() > (info.width * info.height * 4) as usize {
-                                    // imposible
-                                    eprintln!("image size error at {}:{}", file!(), line!());
-                                    return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                                }
-                                // copy
-                                buffer = vg_lite_buffer::default(
-                                    info.width as i32,
-                                    info.height as i32,
-                                    vg_lite_buffer_format_VG_LITE_RGBA8888
-                                );
-                                let error = unsafe {vg_lite_allocate(&mut buffer)};
-                                if error != vg_lite_error_VG_LITE_SUCCESS {
-                                    return error;
-                                }
-                                let buffer_memory = unsafe {
-                                    slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.height * buffer.stride) as usize)
-                                };
-                                if let Err(_) = reader.next_frame(buffer_memory) {
-                                    unsafe {vg_lite_free(&mut buffer)};
-                                    eprintln!("image allocate at {}:{}", file!(), line!());
-                                    return vg_lite_error_VG_LITE_NOT_SUPPORT;
-                                }
-                            }
+                        let error = unsafe {vg_lite_allocate(&mut buffer)};
+                        if error != vg_lite_error_VG_LITE_SUCCESS {
+                            return error;
+                        }
+                        let buffer_memory = unsafe {
+                            slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.height * buffer.stride) as usize)
+                        };
+                        if reader.next_frame(buffer_memory).is_err() {
+                            unsafe {vg_lite_free(&mut buffer)};
+                            eprintln!("image allocate at {}:{}", file!(), line!());
+                            return vg_lite_error_VG_LITE_NOT_SUPPORT;
                         }
-                    } else {
-                        eprintln!("image read info error at {}:{}", file!(), line!());
-                        return vg_lite_error_VG_LITE_NOT_SUPPORT;
                     }
-                    vg_lite_error_VG_LITE_SUCCESS
-                },
-                GIF(_) => { return vg_lite_error_VG_LITE_NOT_SUPPORT;}
-            };
-            if error != vg_lite_error_VG_LITE_SUCCESS {
-                return error;
+                }
+            } else {
+                eprintln!("image read info error at {}:{}", file!(), line!());
+                return vg_lite_error_VG_LITE_NOT_SUPPORT;
             }
-            // BLITs
-            let error = unsafe {
-                vg_lite_blit(
-                    config.target,
-                    &mut buffer,
-                    &mut vg_lite_matrix::from_transform(&m),
-                    vg_lite_blend_VG_LITE_BLEND_NONE,
-                    0,
-                    vg_lite_filter_VG_LITE_FILTER_BI_LINEAR
-                )
+            vg_lite_error_VG_LITE_SUCCESS
+        },
+        GIF(gif) => {
+            let mut gifs = unsafe { &*config.gifs }.borrow_mut();
+            let (width, height, pixels) = if let Some(frame) = gif_frame(&mut gifs, gif, config.gif_frame) {
+                frame
+            } else {
+                eprintln!("image decode error at {}:{}", file!(), line!());
+                return vg_lite_error_VG_LITE_NOT_SUPPORT;
             };
+            buffer = vg_lite_buffer::default(width as i32, height as i32, vg_lite_buffer_format_VG_LITE_RGBA8888);
+            let error = unsafe {vg_lite_allocate(&mut buffer)};
             if error != vg_lite_error_VG_LITE_SUCCESS {
                 return error;
             }
+            let row = width * 4;
+            for (y, line) in pixels.chunks_exact(row).enumerate() {
+                unsafe {
+                    slice::from_raw_parts_mut((buffer.memory as *mut u8).add(y * buffer.stride as usize), row)
+                }.copy_from_slice(line);
+            }
+            vg_lite_error_VG_LITE_SUCCESS
+        }
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        unsafe { vg_lite_free(&mut buffer) };
+        return error;
+    }
+    let (width, height) = size.unwrap_or((buffer.width as f64, buffer.height as f64));
+    m.append(&image_transform(&image.view_box, width, height));
+    m.scale(width / buffer.width as f64, height / buffer.height as f64);
+    // BLITs
+    let error = blit_image(&mut buffer, &m, image.rendering_mode, config);
+    unsafe { vg_lite_free(&mut buffer) };
+    error
+}
+
+/// GIF data shared with the tree, read by a decoder that outlives a render
+struct SharedData(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Animated GIFs of a document by their data, decoded as far as the last
+/// frame drawn
+type GifCache = RefCell<HashMap<*const Vec<u8>, GifAnimation>>;
+
+/// Size and RGBA pixels of frame `index` of a cached GIF, a GIF that fails
+/// to decode leaves the cache, as its decoder can't step on from the error
+fn gif_frame<'a>(
+    gifs: &'a mut HashMap<*const Vec<u8>, GifAnimation>,
+    gif: &Arc<Vec<u8>>,
+    index: usize
+) -> Option<(usize, usize, &'a [u8])> {
+    let key = Arc::as_ptr(gif);
+    let animation = match gifs.entry(key) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(GifAnimation::new(gif.clone())?)
+    };
+    if animation.frame(index).is_none() {
+        gifs.remove(&key);
+        return None;
+    }
+    let animation = &gifs[&key];
+    Some((animation.width, animation.height, &animation.canvas))
+}
+
+/// A GIF part way through its frames, so stepping to the next frame
+/// continues from the canvas instead of compositing from the first frame
+struct GifAnimation {
+    data: Arc<Vec<u8>>,
+    decoder: gif::Decoder<Cursor<SharedData>>,
+    width: usize,
+    height: usize,
+    /// RGBA pixels of the logical screen
+    canvas: Vec<u8>,
+    /// Frames composited onto the canvas
+    decoded: usize,
+    /// Disposal of the last frame and the rect it covers, applied before
+    /// the next frame is drawn
+    dispose: Option<(gif::DisposalMethod, [usize; 4], Option<Vec<u8>>)>,
+    /// Number of frames, known once the last one was decoded
+    count: Option<usize>
+}
+
+impl GifAnimation {
+    fn new(data: Arc<Vec<u8>>) -> Option<GifAnimation> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let decoder = options.read_info(Cursor::new(SharedData(data.clone()))).ok()?;
+        let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(GifAnimation {
+            data,
+            decoder,
+            width,
+            height,
+            canvas: vec![0u8; width * height * 4],
+            decoded: 0,
+            dispose: None,
+            count: None
+        })
+    }
+
+    /// RGBA pixels of frame `index`, frames past the last one wrap around
+    fn frame(&mut self, index: usize) -> Option<&[u8]> {
+        let index = self.count.map_or(index, |count| index % count);
+        if self.decoded > index + 1 {
+            // frames are drawn over what the previous ones left, start over
+            *self = GifAnimation { count: self.count, ..GifAnimation::new(self.data.clone())? };
+        }
+        while self.decoded <= index {
+            if !self.next_frame()? {
+                if self.decoded == 0 {
+                    return None;
+                }
+                self.count = Some(self.decoded);
+                return self.frame(index);
+            }
+        }
+        Some(&self.canvas)
+    }
+
+    /// Composite the next frame onto the canvas, false past the last one
+    fn next_frame(&mut self) -> Option<bool> {
+        let (width, height) = (self.width, self.height);
+        let frame = if let Some(frame) = self.decoder.read_next_frame().ok()? {
+            frame
+        } else {
+            return Some(false);
+        };
+        let canvas = &mut self.canvas;
+        match self.dispose.take() {
+            Some((gif::DisposalMethod::Background, [left, top, right, bottom], _)) => {
+                for y in top..bottom {
+                    canvas[(y * width + left) * 4..(y * width + right) * 4].fill(0);
+                }
+            },
+            Some((gif::DisposalMethod::Previous, _, Some(saved))) => *canvas = saved,
+            _ => {}
+        }
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let (frame_width, frame_height) = (frame.width as usize, frame.height as usize);
+        let saved = if frame.dispose == gif::DisposalMethod::Previous { Some(canvas.clone()) } else { None };
+        for y in 0..frame_height.min(height.saturating_sub(top)) {
+            for x in 0..frame_width.min(width.saturating_sub(left)) {
+                let src = &frame.buffer[(y * frame_width + x) * 4..][..4];
+                // transparent index keeps the pixel below
+                if src[3] != 0 {
+                    canvas[((top + y) * width + left + x) * 4..][..4].copy_from_slice(src);
+                }
+            }
+        }
+        let covered = [
+            left.min(width),
+            top.min(height),
+            (left + frame_width).min(width),
+            (top + frame_height).min(height)
+        ];
+        self.dispose = Some((frame.dispose, covered, saved));
+        self.decoded += 1;
+        Some(true)
+    }
+}
+
+/// Buffer format of a palette image of `bits` per pixel
+fn index_format(bits: usize) -> vg_lite_buffer_format {
+    match bits {
+        1 => vg_lite_buffer_format_VG_LITE_INDEX_1,
+        2 => vg_lite_buffer_format_VG_LITE_INDEX_2,
+        4 => vg_lite_buffer_format_VG_LITE_INDEX_4,
+        _ => vg_lite_buffer_format_VG_LITE_INDEX_8,
+    }
+}
+
+/// CLUT of `count` entries from PNG `PLTE` and `tRNS` chunks, missing
+/// entries are transparent black
+fn clut_colors(palette: &[u8], trns: Option<&[u8]>, count: usize) -> Vec<u32> {
+    (0..count).map(|i| {
+        let rgb = palette.get(i * 3..i * 3 + 3);
+        let alpha = trns.and_then(|trns| trns.get(i).copied()).unwrap_or(0xff);
+        rgb.map_or(0, |rgb| encode_pixel([rgb[0], rgb[1], rgb[2], alpha], TABLE_FORMAT))
+    }).collect()
+}
+
+/// Index of pixel `x` in a PNG row, packed from the most significant bit
+fn png_index(row: &[u8], bits: usize, x: usize) -> u8 {
+    let per_byte = 8 / bits;
+    let shift = 8 - bits * (x % per_byte + 1);
+    (row[x / per_byte] >> shift) & ((1u16 << bits) - 1) as u8
+}
+
+/// Repacks a PNG row so the first pixel of each byte is in the least
+/// significant bits, the index order of hardware without big endian indices
+fn reverse_indices(src: &[u8], dst: &mut [u8], bits: usize) {
+    let per_byte = 8 / bits;
+    let mask = ((1u16 << bits) - 1) as u8;
+    for (dst, &src) in dst.iter_mut().zip(src) {
+        *dst = (0..per_byte).fold(0, |byte, i| byte | ((src >> (i * bits)) & mask) << ((per_byte - 1 - i) * bits));
+    }
+}
+
+/// Maps a `width` x `height` image into its viewport following `preserveAspectRatio`
+fn image_transform(view_box: &usvg::ViewBox, width: f64, height: f64) -> Transform {
+    let rect = view_box.rect;
+    let mut m = Transform::new_translate(rect.x(), rect.y());
+    if let Some(image_rect) = usvg::Rect::new(0., 0., width, height) {
+        m.append(&view_box_to_transform(image_rect, view_box.aspect, rect.size()));
+    }
+    m
+}
+
+/// Clip path of the viewport of an image, which a sliced image overflows
+fn image_clip(rect: usvg::Rect) -> usvg::ClipPath {
+    let root = Node::new(Group(usvg::Group::default()));
+    root.append(Node::new(Path(usvg::Path {
+        data: std::rc::Rc::new(PathData::from_rect(rect)),
+        ..usvg::Path::default()
+    })));
+    usvg::ClipPath {
+        id: String::new(),
+        units: Units::UserSpaceOnUse,
+        transform: Transform::default(),
+        clip_path: None,
+        root
+    }
+}
+
+/// Blit a decoded image, `m` maps its pixels to the target
+fn blit_image(
+    buffer: &mut vg_lite_buffer,
+    m: &Transform,
+    rendering_mode: ImageRendering,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    let (blend, filter, alpha) = blit_state(rendering_mode, config);
+    let mut error = unsafe { vg_lite_source_global_alpha(vg_lite_global_alpha_VG_LITE_SCALED, alpha) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe {
+        vg_lite_blit(
+            config.target,
+            buffer,
+            &mut vg_lite_matrix::from_transform(m),
+            blend,
+            0,
+            filter
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    } else {
+        // image is freed by caller, wait for the blit
+        error = unsafe { vg_lite_finish() };
+    }
+    unsafe { vg_lite_source_global_alpha(vg_lite_global_alpha_VG_LITE_NORMAL, 0xff) };
+    error
+}
+
+/// Blend mode, filter and global alpha of an image blit
+fn blit_state(rendering_mode: ImageRendering, config: &VGLiteConfig) -> (vg_lite_blend_t, vg_lite_filter_t, u8) {
+    // images keep their alpha, unless the caller asked to replace the target
+    let blend = if config.blend == vg_lite_blend_VG_LITE_BLEND_NONE {
+        vg_lite_blend_VG_LITE_BLEND_SRC_OVER
+    } else {
+        config.blend
+    };
+    let filter = match rendering_mode {
+        ImageRendering::OptimizeQuality => vg_lite_filter_VG_LITE_FILTER_BI_LINEAR,
+        ImageRendering::OptimizeSpeed => vg_lite_filter_VG_LITE_FILTER_POINT,
+    };
+    (blend, filter, config.opacity.to_u8())
+}
+
+/// Apply scissor and mask layer states of a config to the hardware
+fn set_clip_state(scissor: Option<[i32; 4]>, mask: *mut vg_lite_buffer) -> vg_lite_error {
+    let error = unsafe {
+        if let Some([left, top, right, bottom]) = scissor {
+            let error = vg_lite_set_scissor(left, top, right, bottom);
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                error
+            } else {
+                vg_lite_enable_scissor()
+            }
+        } else {
+            vg_lite_disable_scissor()
+        }
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    let error = unsafe {
+        if !mask.is_null() {
+            let error = vg_lite_set_masklayer(mask);
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                error
+            } else {
+                vg_lite_enable_masklayer()
+            }
+        } else {
+            vg_lite_disable_masklayer()
+        }
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    }
+    error
+}
+
+/// Run `draw` with the clip path applied, `m` is the matrix of the clipped node
+fn draw_clipped(
+    node: &Node,
+    clip: &usvg::ClipPath,
+    m: &Transform,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>,
+    draw: impl FnOnce(&VGLiteConfig) -> u32
+) -> u32 {
+    let clip_mat = if let Some(clip_mat) = clip_transform(clip, node, m) {
+        clip_mat
+    } else {
+        // bounding box units on an empty element, nothing is visible
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+
+    // axis-aligned rectangle, the scissor is much cheaper than a mask layer
+    if clip.clip_path.is_none() {
+        if let Some([left, top, right, bottom]) = clip_rect(&clip.root, &clip_mat) {
+            let scissor = match config.scissor {
+                Some([l, t, r, b]) => [left.max(l), top.max(t), right.min(r), bottom.min(b)],
+                None => [left, top, right, bottom]
+            };
+            if scissor[0] >= scissor[2] || scissor[1] >= scissor[3] {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            }
+            let error = set_clip_state(Some(scissor), config.mask);
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
+            }
+            let error = draw(&VGLiteConfig {
+                scissor: Some(scissor),
+                ..*config
+            });
+            let restore = set_clip_state(config.scissor, config.mask);
+            return if error != vg_lite_error_VG_LITE_SUCCESS { error } else { restore };
+        }
+    }
+
+    let (width, height) = unsafe { ((*config.target).width, (*config.target).height) };
+    let mut mask: vg_lite_buffer = unsafe { zeroed() };
+    let mut error = build_clip_mask(&mut mask, clip, node, m, width, height, db);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    // nested clip paths, the outer one still applies
+    if !config.mask.is_null() {
+        error = unsafe {
+            vg_lite_blend_masklayer(
+                &mut mask,
+                config.mask,
+                vg_lite_mask_operation_VG_LITE_INTERSECT_MASK,
+                &mut vg_lite_rectangle { x: 0, y: 0, width, height }
+            )
+        };
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = set_clip_state(config.scissor, &mut mask);
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = draw(&VGLiteConfig {
+            mask: &mut mask,
+            ..*config
+        });
+        let restore = set_clip_state(config.scissor, config.mask);
+        if error == vg_lite_error_VG_LITE_SUCCESS {
+            error = restore;
+        }
+    } else {
+        eprintln!("Error at {}:{}", file!(), line!());
+    }
+    // mask layer may still be read by the hardware
+    unsafe {
+        vg_lite_finish();
+        vg_lite_destroy_masklayer(&mut mask);
+    }
+    error
+}
+
+/// Clip path space to target, `None` if it uses the bounding box of an empty node
+fn clip_transform(clip: &usvg::ClipPath, node: &Node, m: &Transform) -> Option<Transform> {
+    let mut clip_mat = *m;
+    clip_mat.append(&clip.transform);
+    if clip.units == Units::ObjectBoundingBox {
+        clip_mat.append(&Transform::from_bbox(object_bbox(node)?.to_rect()?));
+    }
+    Some(clip_mat)
+}
+
+/// Scissor of a clip path made of a single axis-aligned rectangle
+fn clip_rect(root: &Node, mat: &Transform) -> Option<[i32; 4]> {
+    let mut m = *mat;
+    m.append(&root.transform());
+    let mut children = root.children();
+    let child = children.next()?;
+    if children.next().is_some() {
+        return None;
+    }
+    m.append(&child.transform());
+    let rect = if let Path(ref path) = *child.borrow() {
+        if path.visibility != Visibility::Visible {
+            return None;
+        }
+        rect_path(&path.data)?
+    } else {
+        return None;
+    };
+    // rotation and skew make it a quadrilateral
+    if m.b.abs() > f64::EPSILON || m.c.abs() > f64::EPSILON {
+        return None;
+    }
+    let (x1, y1) = m.apply(rect.left(), rect.top());
+    let (x2, y2) = m.apply(rect.right(), rect.bottom());
+    Some([
+        x1.min(x2).round() as i32,
+        y1.min(y2).round() as i32,
+        x1.max(x2).round() as i32,
+        y1.max(y2).round() as i32
+    ])
+}
+
+/// Rectangle of a path made of one closed axis-aligned quadrilateral
+fn rect_path(data: &PathData) -> Option<usvg::Rect> {
+    let mut points = Vec::with_capacity(5);
+    for (i, seg) in data.segments().enumerate() {
+        match seg {
+            MoveTo { x, y } if i == 0 => points.push((x, y)),
+            LineTo { x, y } if i != 0 => points.push((x, y)),
+            ClosePath => {},
+            _ => return None
+        }
+    }
+    if points.len() == 5 && points[0] == points[4] {
+        points.pop();
+    }
+    if points.len() != 4 {
+        return None;
+    }
+    let axis_aligned = (0..4).all(|i| {
+        let (a, b) = (points[i], points[(i + 1) % 4]);
+        a.0 == b.0 || a.1 == b.1
+    });
+    if !axis_aligned {
+        return None;
+    }
+    data.bbox()?.to_rect()
+}
+
+/// Create a mask layer of the clip path, `m` is the matrix of the clipped node
+fn build_clip_mask(
+    mask: &mut vg_lite_buffer,
+    clip: &usvg::ClipPath,
+    node: &Node,
+    m: &Transform,
+    width: i32,
+    height: i32,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    let mut error = new_masklayer(mask, width, height);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    // an empty bounding box leaves the mask empty
+    if let Some(clip_mat) = clip_transform(clip, node, m) {
+        error = render_clip_node(mask, &clip.root, &clip_mat, width, height, db);
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        if let Some(outer) = &clip.clip_path {
+            error = intersect_clip_mask(mask, outer, node, m, width, height, db);
+        }
+    }
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        unsafe { vg_lite_destroy_masklayer(mask) };
+    }
+    error
+}
+
+/// Intersect `mask` with the clip path, `m` is the matrix of the clipped node
+fn intersect_clip_mask(
+    mask: &mut vg_lite_buffer,
+    clip: &usvg::ClipPath,
+    node: &Node,
+    m: &Transform,
+    width: i32,
+    height: i32,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    let mut clip_mask: vg_lite_buffer = unsafe { zeroed() };
+    let mut error = build_clip_mask(&mut clip_mask, clip, node, m, width, height, db);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    error = unsafe {
+        vg_lite_blend_masklayer(
+            mask,
+            &mut clip_mask,
+            vg_lite_mask_operation_VG_LITE_INTERSECT_MASK,
+            &mut vg_lite_rectangle { x: 0, y: 0, width, height }
+        )
+    };
+    unsafe {
+        vg_lite_finish();
+        vg_lite_destroy_masklayer(&mut clip_mask);
+    }
+    error
+}
+
+/// Create a target sized mask layer which hides everything
+fn new_masklayer(mask: &mut vg_lite_buffer, width: i32, height: i32) -> vg_lite_error {
+    let mut error = unsafe { vg_lite_create_masklayer(mask, width as u32, height as u32) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe { vg_lite_fill_masklayer(mask, &mut vg_lite_rectangle { x: 0, y: 0, width, height }, 0) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        unsafe { vg_lite_destroy_masklayer(mask) };
+    }
+    error
+}
+
+/// Add the geometry of a clip path child to the mask layer
+fn render_clip_node(
+    mask: &mut vg_lite_buffer,
+    node: &Node,
+    mat: &Transform,
+    width: i32,
+    height: i32,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    let mut m = *mat;
+    m.append(&node.transform());
+    match node.borrow().to_owned() {
+        Group(group) => {
+            if let Some(clip) = &group.clip_path {
+                // clipped child, build it separately and then add it
+                let mut child_mask: vg_lite_buffer = unsafe { zeroed() };
+                let mut error = new_masklayer(&mut child_mask, width, height);
+                if error != vg_lite_error_VG_LITE_SUCCESS {
+                    return error;
+                }
+                for child in node.children() {
+                    error = render_clip_node(&mut child_mask, &child, &m, width, height, db);
+                    if error != vg_lite_error_VG_LITE_SUCCESS {
+                        break;
+                    }
+                }
+                if error == vg_lite_error_VG_LITE_SUCCESS {
+                    error = intersect_clip_mask(&mut child_mask, clip, node, &m, width, height, db);
+                }
+                if error == vg_lite_error_VG_LITE_SUCCESS {
+                    error = unsafe {
+                        vg_lite_blend_masklayer(
+                            mask,
+                            &mut child_mask,
+                            vg_lite_mask_operation_VG_LITE_UNION_MASK,
+                            &mut vg_lite_rectangle { x: 0, y: 0, width, height }
+                        )
+                    };
+                }
+                unsafe {
+                    vg_lite_finish();
+                    vg_lite_destroy_masklayer(&mut child_mask);
+                }
+                error
+            } else {
+                for child in node.children() {
+                    let error = render_clip_node(mask, &child, &m, width, height, db);
+                    if error != vg_lite_error_VG_LITE_SUCCESS {
+                        return error;
+                    }
+                }
+                vg_lite_error_VG_LITE_SUCCESS
+            }
+        },
+        Path(path) => {
+            if path.visibility != Visibility::Visible || path.data.is_empty() {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            }
+            let bbox = if let Some(bbox) = path.data.bbox() {
+                bbox
+            } else {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            };
+            // clip-rule is stored as the fill rule, stroke is ignored
+            let fill_rule = path.fill.map_or(vg_lite_fill_VG_LITE_FILL_NON_ZERO, |fill| fill_rule(fill.rule));
+            let mut path_data = build_path_data(&path.data);
+            let mut p = new_path(&mut path_data, &bbox, vg_lite_quality_VG_LITE_HIGH);
             let error = unsafe {
-                vg_lite_finish()
+                vg_lite_render_masklayer(
+                    mask,
+                    vg_lite_mask_operation_VG_LITE_UNION_MASK,
+                    &mut p,
+                    fill_rule,
+                    0xffffffff,
+                    &mut vg_lite_matrix::from_transform(&m)
+                )
+            };
+            unsafe {
+                vg_lite_finish();
+                vg_lite_clear_path(&mut p);
+            }
+            error
+        },
+        Text(text) => {
+            if let Some(paths) = db.and_then(|db| text.convert(db, Transform::default())) {
+                render_clip_node(mask, &paths, &m, width, height, None)
+            } else {
+                eprintln!("Warning: <text> in clip path can't be converted, ID: {}", text.id);
+                vg_lite_error_VG_LITE_SUCCESS
+            }
+        },
+        Image(_) => vg_lite_error_VG_LITE_SUCCESS
+    }
+}
+
+/// Bounding box of the node in its user space, the node transform excluded
+fn object_bbox(node: &Node) -> Option<PathBbox> {
+    fn expand(node: &Node, ts: Transform, bbox: &mut Option<PathBbox>) {
+        let child_bbox = match *node.borrow() {
+            Path(ref path) => path.data.bbox_with_transform(ts, None),
+            Image(ref image) => image.view_box.rect.transform(&ts).map(|r| r.to_path_bbox()),
+            _ => None
+        };
+        if let Some(child_bbox) = child_bbox {
+            *bbox = Some(bbox.map_or(child_bbox, |bbox| bbox.expand(child_bbox)));
+        }
+        for child in node.children() {
+            let mut ts = ts;
+            ts.append(&child.transform());
+            expand(&child, ts, bbox);
+        }
+    }
+    let mut bbox = None;
+    expand(node, Transform::default(), &mut bbox);
+    bbox
+}
+
+/// Run `draw` with the mask applied, `m` is the matrix of the masked node
+fn draw_masked(
+    node: &Node,
+    mask: &usvg::Mask,
+    m: &Transform,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>,
+    draw: impl FnOnce(&VGLiteConfig) -> u32
+) -> u32 {
+    let (width, height) = unsafe { ((*config.target).width, (*config.target).height) };
+    let mut values = Vec::new();
+    let mut error = mask_values(&mut values, mask, node, m, width, height, config, db);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    if values.iter().all(|&v| v == 0) {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    }
+
+    let mut layer: vg_lite_buffer = unsafe { zeroed() };
+    error = unsafe { vg_lite_create_masklayer(&mut layer, width as u32, height as u32) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    if layer.format != vg_lite_buffer_format_VG_LITE_A8 || layer.memory.is_null() {
+        eprintln!("Error: mask layer isn't mapped A8 memory at {}:{}", file!(), line!());
+        unsafe { vg_lite_destroy_masklayer(&mut layer) };
+        return vg_lite_error_VG_LITE_NOT_SUPPORT;
+    }
+    let memory = unsafe {
+        slice::from_raw_parts_mut(layer.memory as *mut u8, (layer.stride * layer.height) as usize)
+    };
+    for (row, src) in memory.chunks_exact_mut(layer.stride as usize).zip(values.chunks_exact(width as usize)) {
+        row[..width as usize].copy_from_slice(src);
+    }
+    // nested masks and clip paths, the outer one still applies
+    if !config.mask.is_null() {
+        error = unsafe {
+            vg_lite_blend_masklayer(
+                &mut layer,
+                config.mask,
+                vg_lite_mask_operation_VG_LITE_INTERSECT_MASK,
+                &mut vg_lite_rectangle { x: 0, y: 0, width, height }
+            )
+        };
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = set_clip_state(config.scissor, &mut layer);
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = draw(&VGLiteConfig {
+            mask: &mut layer,
+            ..*config
+        });
+        let restore = set_clip_state(config.scissor, config.mask);
+        if error == vg_lite_error_VG_LITE_SUCCESS {
+            error = restore;
+        }
+    } else {
+        eprintln!("Error at {}:{}", file!(), line!());
+    }
+    unsafe {
+        vg_lite_finish();
+        vg_lite_destroy_masklayer(&mut layer);
+    }
+    error
+}
+
+/// Compute a target sized coverage of the mask, one byte per pixel.
+///
+/// usvg 0.29 doesn't expose `mask-type`, so all masks are luminance masks.
+#[allow(clippy::too_many_arguments)]
+fn mask_values(
+    values: &mut Vec<u8>,
+    mask: &usvg::Mask,
+    node: &Node,
+    m: &Transform,
+    width: i32,
+    height: i32,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    values.clear();
+    values.resize((width * height) as usize, 0);
+    let bbox = object_bbox(node).and_then(|bbox| bbox.to_rect());
+    let uses_bbox = mask.units == Units::ObjectBoundingBox || mask.content_units == Units::ObjectBoundingBox;
+    if uses_bbox && bbox.is_none() {
+        // nothing is visible
+        return vg_lite_error_VG_LITE_SUCCESS;
+    }
+    let rect = match (mask.units, bbox) {
+        (Units::ObjectBoundingBox, Some(bbox)) => mask.rect.bbox_transform(bbox),
+        _ => mask.rect
+    };
+    let inv = if let Some(inv) = invert_transform(m) {
+        inv
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+
+    let mut layer = vg_lite_buffer::default(width, height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let mut error = allocate_layer(&mut layer);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    let mut content_mat = *m;
+    if let (Units::ObjectBoundingBox, Some(bbox)) = (mask.content_units, bbox) {
+        content_mat.append(&Transform::from_bbox(bbox));
+    }
+    error = dfs_offscreen(&mask.root, &content_mat, &mut layer, config, db);
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = unsafe { vg_lite_finish() };
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        let memory = unsafe {
+            slice::from_raw_parts(layer.memory as *const u8, (layer.stride * layer.height) as usize)
+        };
+        for (y, row) in memory.chunks_exact(layer.stride as usize).enumerate() {
+            for (x, pixel) in row.chunks_exact(4).take(width as usize).enumerate() {
+                // content outside of the mask rectangle doesn't count
+                let (ux, uy) = inv.apply(x as f64 + 0.5, y as f64 + 0.5);
+                if ux >= rect.left() && ux < rect.right() && uy >= rect.top() && uy < rect.bottom() {
+                    values[y * width as usize + x] = luminance(pixel);
+                }
+            }
+        }
+    }
+    unsafe { vg_lite_free(&mut layer) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+
+    if let Some(outer) = &mask.mask {
+        // mask on <mask> masks its content, with the same element
+        let mut outer_values = Vec::new();
+        error = mask_values(&mut outer_values, outer, node, m, width, height, config, db);
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            return error;
+        }
+        for (v, o) in values.iter_mut().zip(outer_values) {
+            *v = ((*v as u32 * o as u32 + 127) / 255) as u8;
+        }
+    }
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+/// Luminance of a premultiplied RGBA8888 pixel, which is the mask value
+fn luminance(pixel: &[u8]) -> u8 {
+    let (r, g, b) = (pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+    (0.2125 * r + 0.7154 * g + 0.0721 * b).round().min(255.) as u8
+}
+
+/// Blend modes the hardware has, others are composited by CPU
+fn hw_blend_mode(mode: BlendMode) -> Option<vg_lite_blend_t> {
+    match mode {
+        BlendMode::Normal => Some(vg_lite_blend_VG_LITE_BLEND_SRC_OVER),
+        BlendMode::Multiply => Some(vg_lite_blend_VG_LITE_BLEND_MULTIPLY),
+        BlendMode::Screen => Some(vg_lite_blend_VG_LITE_BLEND_SCREEN),
+        BlendMode::Darken => Some(vg_lite_blend_VG_LITE_BLEND_DARKEN),
+        BlendMode::Lighten => Some(vg_lite_blend_VG_LITE_BLEND_LIGHTEN),
+        _ => None
+    }
+}
+
+/// The group only has one path painted once, so it needs no layer
+fn is_single_draw(node: &Node) -> bool {
+    let mut children = node.children();
+    let single = match (children.next(), children.next()) {
+        (Some(child), None) => child,
+        _ => return false
+    };
+    let result = if let Path(ref path) = *single.borrow() {
+        let paint = path.fill.as_ref().map(|fill| &fill.paint)
+            .xor(path.stroke.as_ref().map(|stroke| &stroke.paint));
+        // pattern tiles are drawn by several draws
+        matches!(paint, Some(Color(_) | LinearGradient(_) | RadialGradient(_)))
+    } else {
+        false
+    };
+    result
+}
+
+/// Config of a group whose lone image fades by the global alpha of its blit
+/// instead of a layer, none if the group needs one
+fn single_image_config(node: &Node, group: &usvg::Group, config: &VGLiteConfig) -> Option<VGLiteConfig> {
+    let blend = hw_blend_mode(group.blend_mode)?;
+    if !group.filters.is_empty() || !is_single_image(node) {
+        return None;
+    }
+    Some(VGLiteConfig {
+        blend: if group.blend_mode == BlendMode::Normal { config.blend } else { blend },
+        opacity: config.opacity * group.opacity,
+        ..*config
+    })
+}
+
+fn is_single_image(node: &Node) -> bool {
+    let mut children = node.children();
+    match (children.next(), children.next()) {
+        (Some(child), None) => matches!(*child.borrow(), Image(_)),
+        _ => false
+    }
+}
+
+/// Blend a target sized layer onto the target by CPU, for blend modes the
+/// hardware lacks. The target must be mapped RGBA8888 memory.
+fn composite_layer_cpu(
+    layer: &mut vg_lite_buffer,
+    opacity: Opacity,
+    mode: BlendMode,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    let target = unsafe { &mut *config.target };
+    if pixel_layout(target.format).is_none() || target.memory.is_null() {
+        eprintln!("Warning: mix-blend-mode {:?} needs 32-bit RGB target, fall back to normal", mode);
+        return composite_layer(layer, opacity, vg_lite_blend_VG_LITE_BLEND_SRC_OVER, config);
+    }
+    let opaque = pixel_is_opaque(target.format);
+    // both the layer and the pending draws of target must be completed
+    let error = unsafe { vg_lite_finish() };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    let (width, height) = (target.width as usize, target.height as usize);
+    let [left, top, right, bottom] = config.scissor.unwrap_or([0, 0, width as i32, height as i32]);
+    let (left, right) = (left.clamp(0, width as i32) as usize, right.clamp(0, width as i32) as usize);
+    let (top, bottom) = (top.clamp(0, height as i32) as usize, bottom.clamp(0, height as i32) as usize);
+    let mask = unsafe { config.mask.as_ref() };
+    for y in top..bottom {
+        let src_row = unsafe {
+            slice::from_raw_parts((layer.memory as *const u8).add(y * layer.stride as usize), width * 4)
+        };
+        let dst_row = unsafe {
+            slice::from_raw_parts_mut((target.memory as *mut u8).add(y * target.stride as usize), width * 4)
+        };
+        let mask_row = mask.map(|mask| unsafe {
+            slice::from_raw_parts((mask.memory as *const u8).add(y * mask.stride as usize), width)
+        });
+        for x in left..right {
+            // global alpha and the mask layer scale the source, as they do on hardware
+            let coverage = opacity.get() * mask_row.map_or(1., |row| row[x] as f64 / 255.);
+            let mut src = [0u8; 4];
+            for (s, &l) in src.iter_mut().zip(&src_row[x * 4..x * 4 + 4]) {
+                *s = (l as f64 * coverage).round() as u8;
+            }
+            if src[3] == 0 {
+                continue;
+            }
+            let dst = &mut dst_row[x * 4..x * 4 + 4];
+            let mut backdrop = decode_pixel(u32::from_le_bytes([dst[0], dst[1], dst[2], dst[3]]), target.format);
+            if opaque {
+                backdrop[3] = 255;
+            }
+            let pixel = blend_pixel(mode, src, backdrop);
+            dst.copy_from_slice(&encode_pixel(pixel, target.format).to_le_bytes());
+        }
+    }
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+/// W3C compositing of premultiplied RGBA pixels, source over backdrop
+fn blend_pixel(mode: BlendMode, src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    let sa = src[3] as f64 / 255.;
+    let da = dst[3] as f64 / 255.;
+    let unpremultiply = |p: [u8; 4], a: f64| if a > 0. {
+        [p[0] as f64 / 255. / a, p[1] as f64 / 255. / a, p[2] as f64 / 255. / a]
+    } else {
+        [0.; 3]
+    };
+    let cs = unpremultiply(src, sa);
+    let cb = unpremultiply(dst, da);
+
+    let separable = |f: fn(f64, f64) -> f64| [f(cb[0], cs[0]), f(cb[1], cs[1]), f(cb[2], cs[2])];
+    let blended = match mode {
+        BlendMode::Normal => cs,
+        BlendMode::Multiply => separable(|b, s| b * s),
+        BlendMode::Screen => separable(|b, s| b + s - b * s),
+        BlendMode::Overlay => separable(|b, s| hard_light(s, b)),
+        BlendMode::Darken => separable(f64::min),
+        BlendMode::Lighten => separable(f64::max),
+        BlendMode::ColorDodge => separable(|b, s| if b == 0. {
+            0.
+        } else if s >= 1. {
+            1.
+        } else {
+            (b / (1. - s)).min(1.)
+        }),
+        BlendMode::ColorBurn => separable(|b, s| if b >= 1. {
+            1.
+        } else if s == 0. {
+            0.
+        } else {
+            1. - ((1. - b) / s).min(1.)
+        }),
+        BlendMode::HardLight => separable(hard_light),
+        BlendMode::SoftLight => separable(|b, s| if s <= 0.5 {
+            b - (1. - 2. * s) * b * (1. - b)
+        } else {
+            let d = if b <= 0.25 { ((16. * b - 12.) * b + 4.) * b } else { b.sqrt() };
+            b + (2. * s - 1.) * (d - b)
+        }),
+        BlendMode::Difference => separable(|b, s| (b - s).abs()),
+        BlendMode::Exclusion => separable(|b, s| b + s - 2. * b * s),
+        BlendMode::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+        BlendMode::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+        BlendMode::Color => set_lum(cs, lum(cb)),
+        BlendMode::Luminosity => set_lum(cb, lum(cs)),
+    };
+
+    let mut result = [0u8; 4];
+    for i in 0..3 {
+        let c = cs[i] * sa * (1. - da) + cb[i] * da * (1. - sa) + sa * da * blended[i];
+        result[i] = (c.clamp(0., 1.) * 255.).round() as u8;
+    }
+    result[3] = ((sa + da - sa * da).clamp(0., 1.) * 255.).round() as u8;
+    result
+}
+
+fn hard_light(b: f64, s: f64) -> f64 {
+    if s <= 0.5 {
+        b * 2. * s
+    } else {
+        let s = 2. * s - 1.;
+        b + s - b * s
+    }
+}
+
+fn lum(c: [f64; 3]) -> f64 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn set_lum(c: [f64; 3], l: f64) -> [f64; 3] {
+    let d = l - lum(c);
+    let c = [c[0] + d, c[1] + d, c[2] + d];
+    // clip color into gamut, keeping its luminosity
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    c.map(|v| {
+        let mut v = v;
+        if n < 0. {
+            v = l + (v - l) * l / (l - n);
+        }
+        if x > 1. {
+            v = l + (v - l) * (1. - l) / (x - l);
+        }
+        v
+    })
+}
+
+fn sat(c: [f64; 3]) -> f64 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f64; 3], s: f64) -> [f64; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    if max > min {
+        c.map(|v| (v - min) * s / (max - min))
+    } else {
+        [0.; 3]
+    }
+}
+
+/// Replace the rendered group in `layer` with the result of its filters
+fn apply_filters(layer: &mut vg_lite_buffer, node: &Node, group: &usvg::Group, m: &Transform) -> vg_lite_error {
+    let mut error = unsafe { vg_lite_finish() };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    let bbox = object_bbox(node).and_then(|bbox| bbox.to_rect());
+    for filter in &group.filters {
+        let region = if let Some(region) = filter_region(filter, bbox, m, layer.width, layer.height) {
+            region
+        } else {
+            // invalid region, the element isn't rendered
+            buffer_pixels_mut(layer).fill(0);
+            return vg_lite_error_VG_LITE_SUCCESS;
+        };
+        error = apply_filter(layer, filter, region, bbox, m);
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            return error;
+        }
+    }
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+/// Filter region in target pixels, left, top, right and bottom
+fn filter_region(
+    filter: &usvg::filter::Filter,
+    bbox: Option<usvg::Rect>,
+    m: &Transform,
+    width: i32,
+    height: i32
+) -> Option<[i32; 4]> {
+    let rect = match filter.units {
+        Units::ObjectBoundingBox => filter.rect.bbox_transform(bbox?),
+        Units::UserSpaceOnUse => filter.rect
+    };
+    let rect = rect.transform(m)?;
+    let region = [
+        (rect.left().floor() as i32).max(0),
+        (rect.top().floor() as i32).max(0),
+        (rect.right().ceil() as i32).min(width),
+        (rect.bottom().ceil() as i32).min(height)
+    ];
+    if region[0] >= region[2] || region[1] >= region[3] {
+        return None;
+    }
+    Some(region)
+}
+
+/// Evaluate the primitives of a filter, `layer` is the source graphic and
+/// receives the result
+fn apply_filter(
+    layer: &mut vg_lite_buffer,
+    filter: &usvg::filter::Filter,
+    region: [i32; 4],
+    bbox: Option<usvg::Rect>,
+    m: &Transform
+) -> vg_lite_error {
+    use usvg::filter::Kind;
+    // user space lengths to target pixels
+    let (sx, sy) = m.get_scale();
+    let scale = |x: f64, y: f64| match (filter.primitive_units, bbox) {
+        (Units::ObjectBoundingBox, Some(bbox)) => (x * bbox.width() * sx, y * bbox.height() * sy),
+        _ => (x * sx, y * sy)
+    };
+    let (width, height, stride) = (layer.width as usize, layer.height as usize, layer.stride as usize);
+    let mut results: Vec<(String, vg_lite_buffer)> = Vec::new();
+    // second input of primitives with two inputs
+    let mut scratch = vec![0u8; stride * height];
+    let mut error = vg_lite_error_VG_LITE_SUCCESS;
+    for primitive in &filter.primitives {
+        let subregion = primitive_region(primitive, filter, region, bbox, m);
+        let mut output = vg_lite_buffer::default(layer.width, layer.height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+        error = unsafe { vg_lite_allocate(&mut output) };
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            eprintln!("Error at {}:{}", file!(), line!());
+            break;
+        }
+        let sub = subregion.map(|v| v as usize);
+        error = match &primitive.kind {
+            Kind::GaussianBlur(blur) => {
+                filter_input(buffer_pixels_mut(&mut output), &blur.input, layer, &results);
+                let (std_dev_x, std_dev_y) = scale(blur.std_dev_x.get(), blur.std_dev_y.get());
+                gaussian_blur(&mut output, subregion, std_dev_x, std_dev_y)
+            },
+            Kind::ColorMatrix(matrix) => {
+                filter_input(buffer_pixels_mut(&mut output), &matrix.input, layer, &results);
+                color_matrix(&mut output, subregion, &color_matrix_values(&matrix.kind), primitive.color_interpolation)
+            },
+            Kind::ComponentTransfer(transfer) => {
+                filter_input(buffer_pixels_mut(&mut output), &transfer.input, layer, &results);
+                component_transfer(&mut output, subregion, transfer, primitive.color_interpolation)
+            },
+            Kind::Offset(offset) => {
+                filter_input(&mut scratch, &offset.input, layer, &results);
+                let (dx, dy) = scale(offset.dx, offset.dy);
+                offset_pixels(buffer_pixels_mut(&mut output), &scratch, stride, width, height, dx, dy);
+                vg_lite_error_VG_LITE_SUCCESS
+            },
+            Kind::Flood(flood) => {
+                let pixels = buffer_pixels_mut(&mut output);
+                pixels.fill(0);
+                flood_pixels(pixels, stride, sub, flood.color, flood.opacity);
+                vg_lite_error_VG_LITE_SUCCESS
+            },
+            Kind::Composite(composite) => {
+                filter_input(buffer_pixels_mut(&mut output), &composite.input2, layer, &results);
+                filter_input(&mut scratch, &composite.input1, layer, &results);
+                composite_pixels(buffer_pixels_mut(&mut output), &scratch, stride, sub, composite.operator);
+                vg_lite_error_VG_LITE_SUCCESS
+            },
+            Kind::Merge(merge) => {
+                buffer_pixels_mut(&mut output).fill(0);
+                for input in &merge.inputs {
+                    filter_input(&mut scratch, input, layer, &results);
+                    composite_pixels(buffer_pixels_mut(&mut output), &scratch, stride, sub, usvg::filter::CompositeOperator::Over);
+                }
+                vg_lite_error_VG_LITE_SUCCESS
+            },
+            Kind::DropShadow(shadow) => {
+                // flood colored alpha of the input, blurred and offset, under the input
+                filter_input(&mut scratch, &shadow.input, layer, &results);
+                let (dx, dy) = scale(shadow.dx, shadow.dy);
+                offset_pixels(buffer_pixels_mut(&mut output), &scratch, stride, width, height, dx, dy);
+                let shadow_color = premultiply([
+                    shadow.color.red as f64 / 255.,
+                    shadow.color.green as f64 / 255.,
+                    shadow.color.blue as f64 / 255.,
+                    shadow.opacity.get()
+                ], false);
+                for pixel in buffer_pixels_mut(&mut output).chunks_exact_mut(4) {
+                    let a = pixel[3] as u32;
+                    for (c, s) in pixel.iter_mut().zip(shadow_color) {
+                        *c = ((s as u32 * a + 127) / 255) as u8;
+                    }
+                }
+                let (std_dev_x, std_dev_y) = scale(shadow.std_dev_x.get(), shadow.std_dev_y.get());
+                let error = gaussian_blur(&mut output, subregion, std_dev_x, std_dev_y);
+                composite_pixels(buffer_pixels_mut(&mut output), &scratch, stride, sub, usvg::filter::CompositeOperator::Over);
+                error
+            },
+            Kind::Morphology(morphology) => {
+                filter_input(buffer_pixels_mut(&mut output), &morphology.input, layer, &results);
+                let (rx, ry) = scale(morphology.radius_x.get(), morphology.radius_y.get());
+                let dilate = morphology.operator == usvg::filter::MorphologyOperator::Dilate;
+                morphology_pixels(buffer_pixels_mut(&mut output), stride, sub, rx.round() as usize, ry.round() as usize, dilate);
+                vg_lite_error_VG_LITE_SUCCESS
+            },
+            kind => {
+                eprintln!("Warning: filter primitive {:?} is not supported", kind);
+                filter_input(buffer_pixels_mut(&mut output), &usvg::filter::Input::SourceGraphic, layer, &results);
+                vg_lite_error_VG_LITE_SUCCESS
+            }
+        };
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            unsafe { vg_lite_free(&mut output) };
+            break;
+        }
+        clear_outside(&mut output, subregion);
+        results.push((primitive.result.clone(), output));
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        if let Some((_, result)) = results.last() {
+            buffer_pixels_mut(layer).copy_from_slice(buffer_pixels(result));
+        }
+    }
+    for (_, mut result) in results {
+        unsafe { vg_lite_free(&mut result) };
+    }
+    error
+}
+
+/// Subregion of a primitive in target pixels, `x`, `y`, `width` and `height`
+/// of the primitive override the filter region
+fn primitive_region(
+    primitive: &usvg::filter::Primitive,
+    filter: &usvg::filter::Filter,
+    region: [i32; 4],
+    bbox: Option<usvg::Rect>,
+    m: &Transform
+) -> [i32; 4] {
+    if primitive.x.is_none() && primitive.y.is_none() && primitive.width.is_none() && primitive.height.is_none() {
+        return region;
+    }
+    let filter_rect = match (filter.units, bbox) {
+        (Units::ObjectBoundingBox, Some(bbox)) => filter.rect.bbox_transform(bbox),
+        _ => filter.rect
+    };
+    let (x, y, w, h) = match (filter.primitive_units, bbox) {
+        (Units::ObjectBoundingBox, Some(bbox)) => (
+            primitive.x.map_or(filter_rect.x(), |x| bbox.x() + x * bbox.width()),
+            primitive.y.map_or(filter_rect.y(), |y| bbox.y() + y * bbox.height()),
+            primitive.width.map_or(filter_rect.width(), |w| w * bbox.width()),
+            primitive.height.map_or(filter_rect.height(), |h| h * bbox.height())
+        ),
+        _ => (
+            primitive.x.unwrap_or(filter_rect.x()),
+            primitive.y.unwrap_or(filter_rect.y()),
+            primitive.width.unwrap_or(filter_rect.width()),
+            primitive.height.unwrap_or(filter_rect.height())
+        )
+    };
+    let rect = if let Some(rect) = usvg::Rect::new(x, y, w, h).and_then(|rect| rect.transform(m)) {
+        rect
+    } else {
+        return [region[0], region[1], region[0], region[1]];
+    };
+    let [left, top, right, bottom] = region;
+    [
+        (rect.left().floor() as i32).clamp(left, right),
+        (rect.top().floor() as i32).clamp(top, bottom),
+        (rect.right().ceil() as i32).clamp(left, right),
+        (rect.bottom().ceil() as i32).clamp(top, bottom)
+    ]
+}
+
+/// Copy a filter primitive input into `pixels`
+fn filter_input(
+    pixels: &mut [u8],
+    input: &usvg::filter::Input,
+    source: &vg_lite_buffer,
+    results: &[(String, vg_lite_buffer)]
+) {
+    use usvg::filter::Input;
+    match input {
+        Input::SourceGraphic => pixels.copy_from_slice(buffer_pixels(source)),
+        Input::SourceAlpha => {
+            for (dst, src) in pixels.chunks_exact_mut(4).zip(buffer_pixels(source).chunks_exact(4)) {
+                dst.copy_from_slice(&[0, 0, 0, src[3]]);
+            }
+        },
+        Input::Reference(name) => {
+            // usvg makes sure the reference is a previous result
+            if let Some((_, result)) = results.iter().rev().find(|(result, _)| result == name) {
+                pixels.copy_from_slice(buffer_pixels(result));
+            } else {
+                pixels.fill(0);
+            }
+        },
+        input => {
+            eprintln!("Warning: filter input {:?} is not supported", input);
+            pixels.fill(0);
+        }
+    }
+}
+
+/// Move `src` by whole pixels into `dst`, uncovered pixels are transparent
+fn offset_pixels(dst: &mut [u8], src: &[u8], stride: usize, width: usize, height: usize, dx: f64, dy: f64) {
+    let (dx, dy) = (dx.round() as isize, dy.round() as isize);
+    dst.fill(0);
+    for y in 0..height as isize {
+        let sy = y - dy;
+        if sy < 0 || sy >= height as isize {
+            continue;
+        }
+        // columns of the destination row which have a source
+        let x0 = dx.clamp(0, width as isize) as usize;
+        let x1 = (width as isize + dx).clamp(0, width as isize) as usize;
+        if x0 >= x1 {
+            continue;
+        }
+        let sx0 = (x0 as isize - dx) as usize;
+        let d = y as usize * stride;
+        let s = sy as usize * stride;
+        dst[d + x0 * 4..d + x1 * 4].copy_from_slice(&src[s + sx0 * 4..s + (sx0 + x1 - x0) * 4]);
+    }
+}
+
+fn flood_pixels(pixels: &mut [u8], stride: usize, region: [usize; 4], color: usvg::Color, opacity: Opacity) {
+    let [left, top, right, bottom] = region;
+    let pixel = premultiply([
+        color.red as f64 / 255.,
+        color.green as f64 / 255.,
+        color.blue as f64 / 255.,
+        opacity.get()
+    ], false);
+    for row in pixels.chunks_exact_mut(stride).take(bottom).skip(top) {
+        for p in row[left * 4..right * 4].chunks_exact_mut(4) {
+            p.copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Porter-Duff composition of premultiplied `src` (in) onto `dst` (in2) in place
+fn composite_pixels(
+    dst: &mut [u8],
+    src: &[u8],
+    stride: usize,
+    region: [usize; 4],
+    operator: usvg::filter::CompositeOperator
+) {
+    use usvg::filter::CompositeOperator;
+    let [left, top, right, bottom] = region;
+    for y in top..bottom {
+        let row = y * stride;
+        for x in left..right {
+            let i = row + x * 4;
+            let s = [src[i] as f64, src[i + 1] as f64, src[i + 2] as f64, src[i + 3] as f64].map(|v| v / 255.);
+            let d = [dst[i] as f64, dst[i + 1] as f64, dst[i + 2] as f64, dst[i + 3] as f64].map(|v| v / 255.);
+            let (sa, da) = (s[3], d[3]);
+            let mut result = [0.; 4];
+            for c in 0..4 {
+                result[c] = match operator {
+                    CompositeOperator::Over => s[c] + d[c] * (1. - sa),
+                    CompositeOperator::In => s[c] * da,
+                    CompositeOperator::Out => s[c] * (1. - da),
+                    CompositeOperator::Atop => s[c] * da + d[c] * (1. - sa),
+                    CompositeOperator::Xor => s[c] * (1. - da) + d[c] * (1. - sa),
+                    CompositeOperator::Arithmetic { k1, k2, k3, k4 } => k1 * s[c] * d[c] + k2 * s[c] + k3 * d[c] + k4,
+                };
+            }
+            // premultiplied color can't exceed alpha
+            let a = result[3].clamp(0., 1.);
+            for c in 0..3 {
+                dst[i + c] = (result[c].clamp(0., a) * 255.).round() as u8;
+            }
+            dst[i + 3] = (a * 255.).round() as u8;
+        }
+    }
+}
+
+/// Erode or dilate in place with a `2 * r + 1` window, separately on each axis
+fn morphology_pixels(pixels: &mut [u8], stride: usize, region: [usize; 4], rx: usize, ry: usize, dilate: bool) {
+    let [left, top, right, bottom] = region;
+    let pick = |a: u8, b: u8| if dilate { a.max(b) } else { a.min(b) };
+    let mut line = Vec::new();
+    for (radius, horizontal) in [(rx, true), (ry, false)] {
+        if radius == 0 {
+            continue;
+        }
+        let (count, lines) = if horizontal { (right - left, bottom - top) } else { (bottom - top, right - left) };
+        for l in 0..lines {
+            let offset = |i: usize| if horizontal {
+                (top + l) * stride + (left + i) * 4
+            } else {
+                (top + i) * stride + (left + l) * 4
+            };
+            line.clear();
+            line.extend((0..count).map(|i| {
+                let o = offset(i);
+                [pixels[o], pixels[o + 1], pixels[o + 2], pixels[o + 3]]
+            }));
+            for i in 0..count {
+                // outside of the region is transparent
+                let mut value = if !dilate && (i < radius || i + radius >= count) { [0; 4] } else { line[i] };
+                for p in &line[i.saturating_sub(radius)..(i + radius + 1).min(count)] {
+                    for c in 0..4 {
+                        value[c] = pick(value[c], p[c]);
+                    }
+                }
+                pixels[offset(i)..offset(i) + 4].copy_from_slice(&value);
+            }
+        }
+    }
+}
+
+/// Blur by hardware when it can, otherwise by CPU
+fn gaussian_blur(buffer: &mut vg_lite_buffer, region: [i32; 4], std_dev_x: f64, std_dev_y: f64) -> vg_lite_error {
+    if std_dev_x <= 0. && std_dev_y <= 0. {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    }
+    // the 3x3 kernel of hardware is isotropic and blurs both axes
+    if std_dev_x > 0. && std_dev_y > 0.
+        && unsafe { vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_GAUSSIAN_BLUR) } != 0 {
+        // the blits blur the whole buffer, only what's inside the region may spread
+        clear_outside(buffer, region);
+        return gaussian_blur_hw(buffer, std_dev_x, std_dev_y);
+    }
+    let region = region.map(|v| v as usize);
+    let stride = buffer.stride as usize;
+    box_blur(buffer_pixels_mut(buffer), stride, region, std_dev_x, std_dev_y);
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+/// Largest deviation blurred at full resolution by hardware, greater ones are
+/// blurred on a downscaled copy
+const GAUSSIAN_HW_MAX_STD_DEV: f64 = 2.;
+
+/// Blur with repeated `VG_LITE_FILTER_GAUSSIAN` blits, each pass is a 3x3
+/// binomial-like kernel with a variance of at most 0.5
+fn gaussian_blur_hw(buffer: &mut vg_lite_buffer, std_dev_x: f64, std_dev_y: f64) -> vg_lite_error {
+    let std_dev = std_dev_x.min(std_dev_y).min(GAUSSIAN_HW_MAX_STD_DEV);
+    let (kx, ky) = (std_dev_x / std_dev, std_dev_y / std_dev);
+    let scaled = kx > 1. || ky > 1.;
+    let (width, height) = (
+        (buffer.width as f64 / kx).ceil() as i32,
+        (buffer.height as f64 / ky).ceil() as i32
+    );
+    let mut ping = vg_lite_buffer::default(width, height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let mut pong = vg_lite_buffer::default(width, height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let mut error = allocate_layer(&mut ping);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = allocate_layer(&mut pong);
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        unsafe { vg_lite_free(&mut ping) };
+        return error;
+    }
+
+    let blit = |target: &mut vg_lite_buffer, source: &mut vg_lite_buffer, t: Transform, filter: vg_lite_filter_t| unsafe {
+        vg_lite_blit(
+            target,
+            source,
+            &mut vg_lite_matrix::from_transform(&t),
+            vg_lite_blend_VG_LITE_BLEND_NONE,
+            0,
+            filter
+        )
+    };
+    error = blit(&mut ping, buffer, Transform::new_scale(1. / kx, 1. / ky), vg_lite_filter_VG_LITE_FILTER_BI_LINEAR);
+
+    // variances of passes add up
+    let passes = (std_dev * std_dev / 0.5).ceil().max(1.);
+    let a = std_dev * std_dev / (2. * passes);
+    // weights of the center, the 4 side neighbours and the 4 corners
+    let (w0, w1, w2) = ((1. - 2. * a).powi(2), a * (1. - 2. * a), a * a);
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = unsafe { vg_lite_gaussian_filter(w0 as f32, w1 as f32, w2 as f32) };
+    }
+    for _ in 0..passes as usize {
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            break;
+        }
+        error = blit(&mut pong, &mut ping, Transform::default(), vg_lite_filter_VG_LITE_FILTER_GAUSSIAN);
+        std::mem::swap(&mut ping, &mut pong);
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = blit(
+            buffer,
+            &mut ping,
+            Transform::new_scale(kx, ky),
+            if scaled { vg_lite_filter_VG_LITE_FILTER_BI_LINEAR } else { vg_lite_filter_VG_LITE_FILTER_POINT }
+        );
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = unsafe { vg_lite_finish() };
+    } else {
+        eprintln!("Error at {}:{}", file!(), line!());
+        unsafe { vg_lite_finish() };
+    }
+    unsafe {
+        vg_lite_free(&mut ping);
+        vg_lite_free(&mut pong);
+    }
+    error
+}
+
+/// Gaussian blur approximated by three box blurs on each axis, as the
+/// filter effects spec suggests, on premultiplied RGBA8888 pixels
+fn box_blur(pixels: &mut [u8], stride: usize, region: [usize; 4], std_dev_x: f64, std_dev_y: f64) {
+    let [left, top, right, bottom] = region;
+    let (width, height) = (right - left, bottom - top);
+    let mut line = Vec::new();
+    for (boxes, horizontal) in [(box_sizes(std_dev_x), true), (box_sizes(std_dev_y), false)] {
+        let boxes = if let Some(boxes) = boxes { boxes } else { continue };
+        let (count, lines) = if horizontal { (width, height) } else { (height, width) };
+        for l in 0..lines {
+            // pixel offsets of the line
+            let offset = |i: usize| if horizontal {
+                (top + l) * stride + (left + i) * 4
+            } else {
+                (top + i) * stride + (left + l) * 4
+            };
+            line.clear();
+            line.extend((0..count).flat_map(|i| {
+                let o = offset(i);
+                [pixels[o] as u32, pixels[o + 1] as u32, pixels[o + 2] as u32, pixels[o + 3] as u32]
+            }));
+            for (before, after) in boxes {
+                box_blur_line(&mut line, before, after);
+            }
+            for i in 0..count {
+                let o = offset(i);
+                for c in 0..4 {
+                    pixels[o + c] = line[i * 4 + c] as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Extents before and after the pixel of the three boxes, `None` if the
+/// deviation is too small to blur
+fn box_sizes(std_dev: f64) -> Option<[(usize, usize); 3]> {
+    let d = (std_dev * 3. * (2. * std::f64::consts::PI).sqrt() / 4. + 0.5).floor() as usize;
+    if d <= 1 {
+        return None;
+    }
+    let h = d / 2;
+    if d % 2 == 1 {
+        Some([(h, h); 3])
+    } else {
+        Some([(h, h - 1), (h - 1, h), (h, h)])
+    }
+}
+
+/// Box blur of one RGBA line in place with a running sum, outside is transparent
+fn box_blur_line(line: &mut [u32], before: usize, after: usize) {
+    let count = line.len() / 4;
+    let size = (before + after + 1) as u32;
+    let src = line.to_vec();
+    let mut sum = [0u32; 4];
+    for i in 0..after.min(count) {
+        for c in 0..4 {
+            sum[c] += src[i * 4 + c];
+        }
+    }
+    for i in 0..count {
+        // slide the window to [i - before, i + after]
+        if i + after < count {
+            for c in 0..4 {
+                sum[c] += src[(i + after) * 4 + c];
+            }
+        }
+        if i > before {
+            for c in 0..4 {
+                sum[c] -= src[(i - before - 1) * 4 + c];
+            }
+        }
+        for c in 0..4 {
+            line[i * 4 + c] = (sum[c] + size / 2) / size;
+        }
+    }
+}
+
+/// Row-major 4x5 matrix of `feColorMatrix`, rows and columns in RGBA order
+/// with the offset column last
+fn color_matrix_values(kind: &usvg::filter::ColorMatrixKind) -> [f64; 20] {
+    use usvg::filter::ColorMatrixKind;
+    match kind {
+        ColorMatrixKind::Matrix(values) => {
+            let mut matrix = [0.; 20];
+            matrix.copy_from_slice(&values[..20]);
+            matrix
+        },
+        ColorMatrixKind::Saturate(s) => {
+            let s = s.get();
+            [
+                0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s, 0., 0.,
+                0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s, 0., 0.,
+                0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s, 0., 0.,
+                0., 0., 0., 1., 0.
+            ]
+        },
+        ColorMatrixKind::HueRotate(angle) => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            [
+                0.213 + 0.787 * cos - 0.213 * sin,
+                0.715 - 0.715 * cos - 0.715 * sin,
+                0.072 - 0.072 * cos + 0.928 * sin,
+                0., 0.,
+                0.213 - 0.213 * cos + 0.143 * sin,
+                0.715 + 0.285 * cos + 0.140 * sin,
+                0.072 - 0.072 * cos - 0.283 * sin,
+                0., 0.,
+                0.213 - 0.213 * cos - 0.787 * sin,
+                0.715 - 0.715 * cos + 0.715 * sin,
+                0.072 + 0.928 * cos + 0.072 * sin,
+                0., 0.,
+                0., 0., 0., 1., 0.
+            ]
+        },
+        ColorMatrixKind::LuminanceToAlpha => [
+            0., 0., 0., 0., 0.,
+            0., 0., 0., 0., 0.,
+            0., 0., 0., 0., 0.,
+            0.2125, 0.7154, 0.0721, 0., 0.
+        ]
+    }
+}
+
+/// Matrix of a component transfer made of identity and linear functions only
+fn component_transfer_matrix(transfer: &usvg::filter::ComponentTransfer) -> Option<[f64; 20]> {
+    use usvg::filter::TransferFunction;
+    let mut matrix = [0.; 20];
+    let funcs = [&transfer.func_r, &transfer.func_g, &transfer.func_b, &transfer.func_a];
+    for (i, func) in funcs.into_iter().enumerate() {
+        let (slope, intercept) = match func {
+            TransferFunction::Identity => (1., 0.),
+            TransferFunction::Linear { slope, intercept } => (*slope, *intercept),
+            _ => return None
+        };
+        matrix[i * 5 + i] = slope;
+        matrix[i * 5 + 4] = intercept;
+    }
+    Some(matrix)
+}
+
+/// Apply a color matrix on a premultiplied RGBA8888 buffer. The hardware works
+/// on premultiplied sRGB values, so it is only used when the result is the same.
+fn color_matrix(
+    buffer: &mut vg_lite_buffer,
+    region: [i32; 4],
+    matrix: &[f64; 20],
+    color_interpolation: usvg::filter::ColorInterpolation
+) -> vg_lite_error {
+    // offsets on color and any change of alpha need unpremultiplied values
+    let premultiplied_safe = matrix[4] == 0. && matrix[9] == 0. && matrix[14] == 0.
+        && matrix[15..] == [0., 0., 0., 1., 0.]
+        && matrix[3] == 0. && matrix[8] == 0. && matrix[13] == 0.;
+    if color_interpolation == usvg::filter::ColorInterpolation::SRGB && premultiplied_safe {
+        let diagonal = (0..3).all(|row| (0..3).all(|col| row == col || matrix[row * 5 + col] == 0.));
+        let hw = unsafe {
+            if diagonal && vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_COLOR_TRANSFORMATION) != 0 {
+                Some(true)
+            } else if vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_PIXEL_MATRIX) != 0 {
+                Some(false)
+            } else {
+                None
+            }
+        };
+        if let Some(color_transform) = hw {
+            return color_matrix_hw(buffer, matrix, color_transform);
+        }
+    }
+    let linear = color_interpolation == usvg::filter::ColorInterpolation::LinearRGB;
+    let stride = buffer.stride as usize;
+    color_matrix_cpu(buffer_pixels_mut(buffer), stride, region.map(|v| v as usize), matrix, linear);
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+fn color_matrix_cpu(pixels: &mut [u8], stride: usize, region: [usize; 4], matrix: &[f64; 20], linear: bool) {
+    let [left, top, right, bottom] = region;
+    for row in pixels.chunks_exact_mut(stride).take(bottom).skip(top) {
+        for pixel in row[left * 4..right * 4].chunks_exact_mut(4) {
+            let c = unpremultiply(pixel, linear);
+            let mut result = [0.; 4];
+            for (i, v) in result.iter_mut().enumerate() {
+                let m = &matrix[i * 5..i * 5 + 5];
+                *v = m[0] * c[0] + m[1] * c[1] + m[2] * c[2] + m[3] * c[3] + m[4];
+            }
+            pixel.copy_from_slice(&premultiply(result, linear));
+        }
+    }
+}
+
+/// Blit the buffer onto itself through the color transform or the pixel matrix
+fn color_matrix_hw(buffer: &mut vg_lite_buffer, matrix: &[f64; 20], color_transform: bool) -> vg_lite_error {
+    let mut source = vg_lite_buffer::default(buffer.width, buffer.height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let mut error = unsafe { vg_lite_allocate(&mut source) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    buffer_pixels_mut(&mut source).copy_from_slice(buffer_pixels(buffer));
+
+    let mut channels = vg_lite_pixel_channel_enable { enable_a: 1, enable_b: 1, enable_g: 1, enable_r: 1 };
+    error = unsafe {
+        if color_transform {
+            let error = vg_lite_set_color_transform(&mut vg_lite_color_transform {
+                a_scale: matrix[18] as f32,
+                a_bias: matrix[19] as f32,
+                r_scale: matrix[0] as f32,
+                r_bias: matrix[4] as f32,
+                g_scale: matrix[6] as f32,
+                g_bias: matrix[9] as f32,
+                b_scale: matrix[12] as f32,
+                b_bias: matrix[14] as f32
+            });
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                error
+            } else {
+                vg_lite_enable_color_transform()
+            }
+        } else {
+            vg_lite_set_pixel_matrix(pixel_matrix(matrix).as_mut_ptr(), &mut channels)
+        }
+    };
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = unsafe {
+            vg_lite_blit(
+                buffer,
+                &mut source,
+                &mut vg_lite_matrix::from_transform(&Transform::default()),
+                vg_lite_blend_VG_LITE_BLEND_NONE,
+                0,
+                vg_lite_filter_VG_LITE_FILTER_POINT
+            )
+        };
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        error = unsafe { vg_lite_finish() };
+    } else {
+        eprintln!("Error at {}:{}", file!(), line!());
+        unsafe { vg_lite_finish() };
+    }
+    // both are global states, turn them off for following blits
+    unsafe {
+        if color_transform {
+            vg_lite_disable_color_transform();
+        } else {
+            let mut channels = vg_lite_pixel_channel_enable { enable_a: 0, enable_b: 0, enable_g: 0, enable_r: 0 };
+            vg_lite_set_pixel_matrix(pixel_matrix(&color_matrix_values(&Default::default())).as_mut_ptr(), &mut channels);
+        }
+        vg_lite_free(&mut source);
+    }
+    error
+}
+
+/// `feColorMatrix` matrix to the ARGB ordered one of `vg_lite_set_pixel_matrix`
+fn pixel_matrix(matrix: &[f64; 20]) -> vg_lite_pixel_matrix_t {
+    // ARGB channel to RGBA
+    const RGBA: [usize; 4] = [3, 0, 1, 2];
+    let mut pixel_matrix = [0.; 20];
+    for (row, &r) in RGBA.iter().enumerate() {
+        for col in 0..5 {
+            let c = if col < 4 { RGBA[col] } else { 4 };
+            pixel_matrix[row * 5 + col] = matrix[r * 5 + c] as f32;
+        }
+    }
+    pixel_matrix
+}
+
+/// Apply a component transfer on a premultiplied RGBA8888 buffer
+fn component_transfer(
+    buffer: &mut vg_lite_buffer,
+    region: [i32; 4],
+    transfer: &usvg::filter::ComponentTransfer,
+    color_interpolation: usvg::filter::ColorInterpolation
+) -> vg_lite_error {
+    use usvg::filter::TransferFunction;
+    if let Some(matrix) = component_transfer_matrix(transfer) {
+        return color_matrix(buffer, region, &matrix, color_interpolation);
+    }
+    let apply = |func: &TransferFunction, c: f64| match func {
+        TransferFunction::Identity => c,
+        TransferFunction::Table(values) if !values.is_empty() => {
+            let n = values.len() - 1;
+            let k = ((c * n as f64) as usize).min(n.saturating_sub(1));
+            if n == 0 {
+                values[0]
+            } else {
+                values[k] + (c - k as f64 / n as f64) * n as f64 * (values[k + 1] - values[k])
+            }
+        },
+        TransferFunction::Discrete(values) if !values.is_empty() => {
+            let n = values.len();
+            values[((c * n as f64) as usize).min(n - 1)]
+        },
+        TransferFunction::Linear { slope, intercept } => slope * c + intercept,
+        TransferFunction::Gamma { amplitude, exponent, offset } => amplitude * c.powf(*exponent) + offset,
+        _ => c
+    };
+    let linear = color_interpolation == usvg::filter::ColorInterpolation::LinearRGB;
+    let [left, top, right, bottom] = region.map(|v| v as usize);
+    let stride = buffer.stride as usize;
+    let funcs = [&transfer.func_r, &transfer.func_g, &transfer.func_b, &transfer.func_a];
+    for row in buffer_pixels_mut(buffer).chunks_exact_mut(stride).take(bottom).skip(top) {
+        for pixel in row[left * 4..right * 4].chunks_exact_mut(4) {
+            let c = unpremultiply(pixel, linear);
+            let mut result = [0.; 4];
+            for i in 0..4 {
+                result[i] = apply(funcs[i], c[i]);
+            }
+            pixel.copy_from_slice(&premultiply(result, linear));
+        }
+    }
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+/// Premultiplied RGBA8888 pixel to straight values in 0..1, optionally in linearRGB
+fn unpremultiply(pixel: &[u8], linear: bool) -> [f64; 4] {
+    let a = pixel[3] as f64 / 255.;
+    let mut c = [0., 0., 0., a];
+    if a > 0. {
+        for i in 0..3 {
+            c[i] = (pixel[i] as f64 / 255. / a).min(1.);
+            if linear {
+                c[i] = srgb_to_linear(c[i]);
+            }
+        }
+    }
+    c
+}
+
+/// Straight values in 0..1 to a premultiplied RGBA8888 pixel, values are clamped
+fn premultiply(c: [f64; 4], linear: bool) -> [u8; 4] {
+    let a = c[3].clamp(0., 1.);
+    let mut pixel = [0, 0, 0, (a * 255.).round() as u8];
+    for i in 0..3 {
+        let v = c[i].clamp(0., 1.);
+        let v = if linear { linear_to_srgb(v) } else { v };
+        pixel[i] = (v * a * 255.).round() as u8;
+    }
+    pixel
+}
+
+fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Make everything outside of the region transparent
+fn clear_outside(buffer: &mut vg_lite_buffer, region: [i32; 4]) {
+    let [left, top, right, bottom] = region.map(|v| v as usize);
+    let (stride, width) = (buffer.stride as usize, buffer.width as usize);
+    for (y, row) in buffer_pixels_mut(buffer).chunks_exact_mut(stride).enumerate() {
+        if y < top || y >= bottom {
+            row.fill(0);
+        } else {
+            row[..left * 4].fill(0);
+            row[right * 4..width * 4].fill(0);
+        }
+    }
+}
+
+/// Memory of a mapped buffer, the hardware must be done with it
+fn buffer_pixels(buffer: &vg_lite_buffer) -> &[u8] {
+    unsafe { slice::from_raw_parts(buffer.memory as *const u8, (buffer.stride * buffer.height) as usize) }
+}
+
+fn buffer_pixels_mut(buffer: &mut vg_lite_buffer) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(buffer.memory as *mut u8, (buffer.stride * buffer.height) as usize) }
+}
+
+#[allow(unused)]
+fn right_normal(x: f64, y: f64, x0: f64, y0: f64) -> (f64, f64) {
+    let vector = (x - x0, y - y0);
+    // right rotate
+    let normal = (vector.1, -vector.0);
+    let length = (normal.0.powi(2) + normal.1.powi(2)).sqrt();
+    (normal.0 / length, normal.1 / length)
+}
+
+/// Pixels of the target a node can draw to
+enum Extent {
+    Empty,
+    Bounded(PathBbox),
+    Unbounded
+}
+
+impl Extent {
+    /// Whether nothing lands inside `rect`, given as left, top, right and bottom
+    fn is_outside(&self, rect: &[f64; 4]) -> bool {
+        match self {
+            Extent::Empty => true,
+            Extent::Bounded(bbox) => {
+                bbox.right() <= rect[0] || bbox.bottom() <= rect[1] || bbox.left() >= rect[2] || bbox.top() >= rect[3]
+            },
+            Extent::Unbounded => false
+        }
+    }
+
+    /// Whether everything lands inside `rect`
+    fn is_inside(&self, rect: &[f64; 4]) -> bool {
+        match self {
+            Extent::Empty => true,
+            Extent::Bounded(bbox) => {
+                bbox.left() >= rect[0] && bbox.top() >= rect[1] && bbox.right() <= rect[2] && bbox.bottom() <= rect[3]
+            },
+            Extent::Unbounded => false
+        }
+    }
+
+    fn union(self, other: Extent) -> Extent {
+        match (self, other) {
+            (Extent::Empty, extent) | (extent, Extent::Empty) => extent,
+            (Extent::Bounded(a), Extent::Bounded(b)) => Extent::Bounded(a.expand(b)),
+            _ => Extent::Unbounded
+        }
+    }
+}
+
+/// Extent of a node, `m` is the matrix of the node. A group reaches as far as
+/// its descendants, a filtered group as far as its filter region
+fn node_extent(node: &Node, m: &Transform) -> Extent {
+    match *node.borrow() {
+        // nothing to draw without a bounding box
+        Path(ref path) => match path.data.bbox().and_then(|bbox| device_bbox(&stroked_bbox(&bbox, path.stroke.as_ref()), m)) {
+            Some(bbox) => Extent::Bounded(bbox),
+            None => Extent::Empty
+        },
+        Image(ref image) => match image.view_box.rect.transform(m) {
+            Some(rect) => Extent::Bounded(rect.to_path_bbox()),
+            None => Extent::Empty
+        },
+        Group(ref group) => {
+            if group.opacity == Opacity::ZERO {
+                return Extent::Empty;
+            }
+            if !group.filters.is_empty() {
+                return filter_extent(node, group, m);
+            }
+            node.children().fold(Extent::Empty, |extent, child| {
+                let mut m = *m;
+                m.append(&child.transform());
+                extent.union(node_extent(&child, &m))
+            })
+        },
+        // glyphs are only known once the text is converted
+        Text(_) => Extent::Unbounded
+    }
+}
+
+/// Each filter clips its result to its region, so the last region bounds the
+/// group, an invalid region renders nothing
+fn filter_extent(node: &Node, group: &usvg::Group, m: &Transform) -> Extent {
+    let bbox = object_bbox(node).and_then(|bbox| bbox.to_rect());
+    let mut extent = Extent::Unbounded;
+    for filter in &group.filters {
+        extent = match filter_region(filter, bbox, m, i32::MAX, i32::MAX) {
+            Some([left, top, right, bottom]) => match PathBbox::new(
+                left as f64, top as f64, (right - left) as f64, (bottom - top) as f64
+            ) {
+                Some(bbox) => Extent::Bounded(bbox),
+                None => Extent::Empty
+            },
+            None => return Extent::Empty
+        };
+    }
+    extent
+}
+
+/// Object bounding box of the paints of a path, in path space without the
+/// stroke, the path matrix maps it to the target together with the path
+fn paint_bbox(path: &usvg::Path) -> Option<PathBbox> {
+    path.data.bbox()
+}
+
+/// Grows a path space bounding box by the farthest a stroke can reach out of
+/// the path, miter joins and square caps go beyond half of the width
+fn stroked_bbox(bbox: &PathBbox, stroke: Option<&usvg::Stroke>) -> PathBbox {
+    let stroke = if let Some(stroke) = stroke {
+        stroke
+    } else {
+        return *bbox;
+    };
+    let mut reach: f64 = 1.;
+    if stroke.linejoin == LineJoin::Miter {
+        reach = reach.max(stroke.miterlimit.get());
+    }
+    if stroke.linecap == LineCap::Square {
+        reach = reach.max(std::f64::consts::SQRT_2);
+    }
+    let grow = stroke.width.get() / 2. * reach;
+    PathBbox::new(bbox.x() - grow, bbox.y() - grow, bbox.width() + 2. * grow, bbox.height() + 2. * grow)
+        .unwrap_or(*bbox)
+}
+
+/// Bounding box of the transformed corners of `bbox`, which may have no area
+fn device_bbox(bbox: &PathBbox, m: &Transform) -> Option<PathBbox> {
+    let corners = [
+        m.apply(bbox.left(), bbox.top()),
+        m.apply(bbox.right(), bbox.top()),
+        m.apply(bbox.left(), bbox.bottom()),
+        m.apply(bbox.right(), bbox.bottom())
+    ];
+    let (mut left, mut top) = corners[0];
+    let (mut right, mut bottom) = corners[0];
+    for (x, y) in corners {
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    PathBbox::new(left, top, right - left, bottom - top)
+}
+
+/// Whether the children of a group with the extent are worth culling, not
+/// when they are all visible, nor when filters move their pixels around
+fn cull_children(group: &usvg::Group, extent: &Extent, rect: &[f64; 4]) -> bool {
+    group.filters.is_empty() && !extent.is_inside(rect)
+}
+
+/// Pixels a draw with the config can touch, left, top, right and bottom
+fn visible_rect(config: &VGLiteConfig) -> [f64; 4] {
+    let (width, height) = unsafe { ((*config.target).width, (*config.target).height) };
+    let [left, top, right, bottom] = config.scissor
+        .map(|[l, t, r, b]| [l.max(0), t.max(0), r.min(width), b.min(height)])
+        .unwrap_or([0, 0, width, height]);
+    [left as f64, top as f64, right as f64, bottom as f64]
+}
+
+fn dfs(node: &Node, mat: &Transform, config: &VGLiteConfig, db: Option<&fontdb::Database>) -> u32 {
+    let mut m = *mat;
+    m.append(&node.transform());
+    let rect = visible_rect(config);
+    // a group walks its subtree, which is then only walked again by the
+    // descendants of groups that straddle the edge of the visible pixels
+    let extent = if config.cull { node_extent(node, &m) } else { Extent::Unbounded };
+    if extent.is_outside(&rect) {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    }
+    match node.borrow().to_owned() {
+        Group(group) => {
+            if group.opacity == Opacity::ZERO {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            }
+            let config = &VGLiteConfig {
+                cull: config.cull && cull_children(&group, &extent, &rect),
+                ..*config
             };
-            if error != vg_lite_error_VG_LITE_SUCCESS {
-                return error;
+            let draw_masked_group = |config: &VGLiteConfig| if let Some(mask) = &group.mask {
+                draw_masked(node, mask, &m, config, db, |config| draw_group(node, &group, &m, config, db))
+            } else {
+                draw_group(node, &group, &m, config, db)
+            };
+            if let Some(clip) = &group.clip_path {
+                draw_clipped(node, clip, &m, config, db, draw_masked_group)
+            } else {
+                draw_masked_group(config)
             }
-            unsafe {
-                vg_lite_free(&mut buffer)
+        },
+        Path(path) => {
+            if path.visibility != Visibility::Visible || path.data.is_empty() {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            }
+
+            let bbox = if let Some(bbox) = paint_bbox(&path) {
+                bbox
+            } else {
+                eprintln!("Warning: path can't read bounding box, ID: {}", path.id);
+                return vg_lite_error_VG_LITE_SUCCESS;
+            };
+
+            // build path
+            let mut path_data = build_path_data(&path.data);
+            let mut mr = vg_lite_matrix::from_transform(&m);
+            // the hardware clips tessellation to the box, so it covers the stroke
+            let mut p = new_path(&mut path_data, &stroked_bbox(&bbox, path.stroke.as_ref()), config.quality);
+
+            let error = match path.paint_order {
+                PaintOrder::FillAndStroke => {
+                    let error = draw_fill(&mut p, &path, &bbox, &m, &mut mr, config, db);
+                    if error != vg_lite_error_VG_LITE_SUCCESS {
+                        error
+                    } else {
+                        draw_stroke(&mut p, &path, &bbox, &m, &mut mr, config, db)
+                    }
+                },
+                PaintOrder::StrokeAndFill => {
+                    let error = draw_stroke(&mut p, &path, &bbox, &m, &mut mr, config, db);
+                    if error != vg_lite_error_VG_LITE_SUCCESS {
+                        error
+                    } else {
+                        draw_fill(&mut p, &path, &bbox, &m, &mut mr, config, db)
+                    }
+                }
+            };
+            // free the stroke allocated by driver, path data is owned by us
+            unsafe { vg_lite_clear_path(&mut p) };
+            error
+        },
+        Image(image) => {
+            if image.visibility != Visibility::Visible {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            }
+            if image.view_box.aspect.slice && image.view_box.aspect.align != Align::None {
+                let clip = image_clip(image.view_box.rect);
+                draw_clipped(node, &clip, &m, config, db, |config| draw_image(&image, &m, config, db))
+            } else {
+                draw_image(&image, &m, config, db)
             }
         },
         Text(text) => {
@@ -615,6 +2953,913 @@ fn dfs(node: &Node, mat: &Transform, config: &VGLiteConfig, db: Option<&fontdb::
     }
 }
 
+/// The path borrows `path_data`, keep it alive until the path is cleared
+fn new_path(path_data: &mut [f32], bbox: &PathBbox, quality: vg_lite_quality_t) -> vg_lite_path {
+    vg_lite_path {
+        bounding_box: [
+            bbox.x() as f32,
+            bbox.y() as f32,
+            bbox.right() as f32,
+            bbox.bottom() as f32
+        ],
+        quality,
+        format: vg_lite_format_VG_LITE_FP32,
+        uploaded: unsafe { transmute::<[u32;8], vg_lite_hw_memory>([0;8]) },
+        path_length: (path_data.len() * 4) as u32,
+        path: path_data.as_mut_ptr() as *mut c_void,
+        path_changed: 1,
+        pdata_internal: 0,
+        path_type: vg_lite_path_type_VG_LITE_DRAW_FILL_PATH,
+        stroke: null_mut(),
+        stroke_path: null_mut(),
+        stroke_size: 0,
+        stroke_color: 0,
+        add_end: 0
+    }
+}
+
+fn build_path_data(data: &PathData) -> Vec<f32> {
+    let mut path_data = Vec::new();
+    for seg in data.segments() {
+        match seg {
+            MoveTo { x, y } => {
+                path_data.push(f32::from_bits(VLC_OP_MOVE));
+                path_data.push(x as f32);
+                path_data.push(y as f32);
+            },
+            LineTo { x, y } => {
+                path_data.push(f32::from_bits(VLC_OP_LINE));
+                path_data.push(x as f32);
+                path_data.push(y as f32);
+            },
+            CurveTo { x1, y1, x2, y2, x, y } => {
+                path_data.push(f32::from_bits(VLC_OP_CUBIC));
+                path_data.push(x1 as f32);
+                path_data.push(y1 as f32);
+                path_data.push(x2 as f32);
+                path_data.push(y2 as f32);
+                path_data.push(x as f32);
+                path_data.push(y as f32);
+            },
+            ClosePath => {
+                path_data.push(f32::from_bits(VLC_OP_CLOSE));
+            }
+        }
+    }
+    path_data.push(f32::from_bits(VLC_OP_END));
+    path_data
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_fill(
+    p: &mut vg_lite_path,
+    path: &usvg::Path,
+    bbox: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    let fill = if let Some(fill) = &path.fill {
+        fill
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+    let error = unsafe { vg_lite_set_path_type(p, vg_lite_path_type_VG_LITE_DRAW_FILL_PATH) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    let fill_rule = path_fill_rule(fill, config.fill_rule);
+    draw_paint(p, &fill.paint, fill.opacity, fill_rule, bbox, bbox, m, mr, config, db)
+}
+
+/// Rule the caller forces on every path, `SVGLITE_FILL_RULE_AUTO` or any other
+/// value keeps the rule of each path
+fn fill_rule_override(fill_rule: vg_lite_fill_t) -> Option<vg_lite_fill_t> {
+    match fill_rule {
+        vg_lite_fill_VG_LITE_FILL_EVEN_ODD | vg_lite_fill_VG_LITE_FILL_NON_ZERO => Some(fill_rule),
+        _ => None
+    }
+}
+
+fn path_fill_rule(fill: &usvg::Fill, fill_rule_override: Option<vg_lite_fill_t>) -> vg_lite_fill_t {
+    fill_rule_override.unwrap_or_else(|| fill_rule(fill.rule))
+}
+
+fn fill_rule(rule: usvg::FillRule) -> vg_lite_fill_t {
+    match rule {
+        usvg::FillRule::NonZero => vg_lite_fill_VG_LITE_FILL_NON_ZERO,
+        usvg::FillRule::EvenOdd => vg_lite_fill_VG_LITE_FILL_EVEN_ODD
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_stroke(
+    p: &mut vg_lite_path,
+    path: &usvg::Path,
+    bbox: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    let stroke = if let Some(stroke) = &path.stroke {
+        stroke
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+    let mut style = stroke_style(stroke);
+    let mut error = unsafe {
+        vg_lite_set_stroke(
+            p,
+            style.cap,
+            style.join,
+            style.width,
+            style.miter_limit,
+            if style.dash_pattern.is_empty() { null_mut() } else { style.dash_pattern.as_mut_ptr() },
+            style.dash_pattern.len() as u32,
+            style.dash_phase,
+            style.color
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe { vg_lite_update_stroke(p) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe { vg_lite_set_path_type(p, vg_lite_path_type_VG_LITE_DRAW_STROKE_PATH) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    // outline generated by driver always uses non-zero rule
+    let area = stroked_bbox(bbox, Some(stroke));
+    draw_paint(p, &stroke.paint, stroke.opacity, vg_lite_fill_VG_LITE_FILL_NON_ZERO, bbox, &area, m, mr, config, db)
+}
+
+/// Arguments of `vg_lite_set_stroke()` for a stroke
+#[derive(Debug, PartialEq)]
+struct StrokeStyle {
+    cap: vg_lite_cap_style_t,
+    join: vg_lite_join_style_t,
+    width: f32,
+    miter_limit: f32,
+    dash_pattern: Vec<f32>,
+    dash_phase: f32,
+    color: vg_lite_color_t
+}
+
+fn stroke_style(stroke: &usvg::Stroke) -> StrokeStyle {
+    StrokeStyle {
+        cap: match stroke.linecap {
+            LineCap::Butt => vg_lite_cap_style_VG_LITE_CAP_BUTT,
+            LineCap::Round => vg_lite_cap_style_VG_LITE_CAP_ROUND,
+            LineCap::Square => vg_lite_cap_style_VG_LITE_CAP_SQUARE,
+        },
+        join: match stroke.linejoin {
+            LineJoin::Miter => vg_lite_join_style_VG_LITE_JOIN_MITER,
+            LineJoin::Round => vg_lite_join_style_VG_LITE_JOIN_ROUND,
+            LineJoin::Bevel => vg_lite_join_style_VG_LITE_JOIN_BEVEL,
+        },
+        width: stroke.width.get() as f32,
+        miter_limit: stroke.miterlimit.get() as f32,
+        // usvg already repeats odd-count dash arrays and drops all-zero ones
+        dash_pattern: stroke.dasharray.as_ref().map_or(Vec::new(), |dasharray| {
+            dasharray.iter().map(|x| *x as f32).collect()
+        }),
+        dash_phase: stroke.dashoffset,
+        // stroke color is stored in path, gradients and patterns don't use it
+        color: if let Color(color) = stroke.paint {
+            pack_color(color, stroke.opacity)
+        } else {
+            0
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_paint(
+    p: &mut vg_lite_path,
+    paint: &Paint,
+    opacity: Opacity,
+    fill_rule: vg_lite_fill_t,
+    bbox: &PathBbox,
+    area: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig,
+    db: Option<&fontdb::Database>
+) -> vg_lite_error {
+    match paint {
+        Color(color) => {
+            let error = unsafe { vg_lite_draw(
+                config.target,
+                p,
+                fill_rule,
+                mr,
+                config.blend,
+                pack_color(*color, opacity)
+            ) };
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                eprintln!("Error at {}:{}", file!(), line!());
+                return error;
+            }
+        },
+        LinearGradient(lg) => {
+            let ext = unsafe { vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_LINEAR_GRADIENT_EXT) } != 0;
+            let error = if ext && lg.stops.len() <= VLC_MAX_COLOR_RAMP_STOPS as usize {
+                draw_ext_linear_grad(p, lg, opacity, fill_rule, bbox, m, mr, config)
+            } else if !ext && lg.stops.len() <= VLC_MAX_GRADIENT_STOPS as usize && lg.spread_method == SpreadMethod::Pad {
+                draw_linear_grad(p, lg, opacity, fill_rule, bbox, m, mr, config)
+            } else {
+                // too many stops or spread method not supported by hardware, bake the ramp with CPU
+                draw_grad_ramp(p, lg, opacity, fill_rule, bbox, area, m, mr, config)
+            };
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
+            }
+        },
+        RadialGradient(rg) => {
+            let error = if unsafe { vg_lite_query_feature(vg_lite_feature_gcFEATURE_BIT_VG_RADIAL_GRADIENT) } == 0 {
+                // no radial gradient unit, bake the gradient with CPU
+                draw_radial_bake(p, rg, opacity, fill_rule, bbox, area, m, mr, config)
+            } else {
+                draw_radial_grad(p, rg, opacity, fill_rule, bbox, m, mr, config)
+            };
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
+            }
+        },
+        Pattern(pattern) => {
+            let (width, height, content_mat, pattern_mat) = if let Some(tile) = pattern_tile(pattern, bbox, m) {
+                tile
+            } else {
+                return vg_lite_error_VG_LITE_SUCCESS;
+            };
+            let mut tile = vg_lite_buffer::default(width, height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+            let mut error = allocate_layer(&mut tile);
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
+            }
+            error = dfs_offscreen(&pattern.root, &content_mat, &mut tile, config, db);
+            if error == vg_lite_error_VG_LITE_SUCCESS {
+                // tile must be completed before used as pattern
+                error = unsafe { vg_lite_finish() };
+            }
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                unsafe { vg_lite_free(&mut tile) };
+                return error;
+            }
+
+            // paint opacity fades the tile as a whole
+            error = unsafe { vg_lite_source_global_alpha(vg_lite_global_alpha_VG_LITE_SCALED, opacity.to_u8()) };
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                eprintln!("Error at {}:{}", file!(), line!());
+                unsafe { vg_lite_free(&mut tile) };
+                return error;
+            }
+            error = unsafe {
+                vg_lite_draw_pattern(
+                    config.target,
+                    p,
+                    fill_rule,
+                    mr,
+                    &mut tile,
+                    &mut vg_lite_matrix::from_transform(&pattern_mat),
+                    config.blend,
+                    vg_lite_pattern_mode_VG_LITE_PATTERN_REPEAT,
+                    0,
+                    vg_lite_filter_VG_LITE_FILTER_BI_LINEAR
+                )
+            };
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                eprintln!("Error at {}:{}", file!(), line!());
+            } else {
+                error = unsafe { vg_lite_finish() };
+            }
+            unsafe { vg_lite_source_global_alpha(vg_lite_global_alpha_VG_LITE_NORMAL, 0xff) };
+            unsafe { vg_lite_free(&mut tile) };
+            if error != vg_lite_error_VG_LITE_SUCCESS {
+                return error;
+            }
+        },
+    }
+    vg_lite_error_VG_LITE_SUCCESS
+}
+
+/// Size of a pattern tile rendered in target resolution, the matrix of its
+/// content and the matrix mapping the tile back to the pattern rectangle
+fn pattern_tile(pattern: &usvg::Pattern, bbox: &PathBbox, m: &Transform) -> Option<(i32, i32, Transform, Transform)> {
+    let bbox = bbox.to_rect()?;
+    let rect = if pattern.units == Units::ObjectBoundingBox {
+        pattern.rect.bbox_transform(bbox)
+    } else {
+        pattern.rect
+    };
+    let mut global_mat = *m;
+    global_mat.append(&pattern.transform);
+    let (sx, sy) = global_mat.get_scale();
+    // a huge tile is drawn blurrier rather than failing the allocation
+    let width = (rect.width() * sx).ceil().min(MAX_IMAGE_SIZE) as i32;
+    let height = (rect.height() * sy).ceil().min(MAX_IMAGE_SIZE) as i32;
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    let (sx, sy) = (width as f64 / rect.width(), height as f64 / rect.height());
+    let mut content_mat = Transform::new_scale(sx, sy);
+    if let Some(view_box) = pattern.view_box {
+        content_mat.append(&view_box_to_transform(view_box.rect, view_box.aspect, rect.size()));
+    } else if pattern.content_units == Units::ObjectBoundingBox {
+        content_mat.scale(bbox.width(), bbox.height());
+    }
+    let mut pattern_mat = global_mat;
+    pattern_mat.translate(rect.x(), rect.y());
+    pattern_mat.scale(1. / sx, 1. / sy);
+    Some((width, height, content_mat, pattern_mat))
+}
+
+/// Legacy linear gradient, `VLC_MAX_GRADIENT_STOPS` stops at most and only pad
+#[allow(clippy::too_many_arguments)]
+fn draw_linear_grad(
+    p: &mut vg_lite_path,
+    lg: &usvg::LinearGradient,
+    opacity: Opacity,
+    fill_rule: vg_lite_fill_t,
+    bbox: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    // build gradient
+    let mut grad: vg_lite_linear_gradient = unsafe { transmute([0usize;54]) };
+    let mut error = unsafe { vg_lite_init_grad(&mut grad) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+
+    // the legacy ramp takes no paint opacity, fold it into the stops
+    let mut colors: Vec<vg_lite_color_t> = lg.base.stops.iter().map(|x| {
+        x.get_u32(opacity)
+    }).collect();
+
+    // stop is 0 to 255, use matrix to scale
+    let mut stops: Vec<u32> = lg.base.stops.iter().map(|x| {
+        x.offset.to_u8() as u32
+    }).collect();
+
+    error = unsafe {
+        vg_lite_set_grad(
+            &mut grad,
+            stops.len() as u32,
+            colors.as_mut_ptr(),
+            stops.as_mut_ptr()
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    } else {
+        error = unsafe { vg_lite_update_grad(&mut grad) };
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            eprintln!("Error at {}:{}", file!(), line!());
+        }
+    }
+    if error == vg_lite_error_VG_LITE_SUCCESS {
+        // the ramp is 0 to 255 on x axis, map it to the gradient vector
+        let mut grad_mat = gradient_transform(lg, bbox, m);
+        grad_mat.append(&ramp_transform(lg, 0., 1., 256.));
+        unsafe { &mut *vg_lite_get_grad_matrix(&mut grad) }.update_transform(&grad_mat);
+        error = unsafe {
+            vg_lite_draw_grad(
+                config.target,
+                p,
+                fill_rule,
+                mr,
+                &mut grad,
+                config.blend
+            )
+        };
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            eprintln!("Error {}:{}", file!(), line!());
+        } else {
+            // the ramp image is used by hardware, wait before release it
+            error = unsafe { vg_lite_finish() };
+        }
+    }
+    let clear = unsafe { vg_lite_clear_grad(&mut grad) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        error
+    } else {
+        clear
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_ext_linear_grad(
+    p: &mut vg_lite_path,
+    lg: &usvg::LinearGradient,
+    opacity: Opacity,
+    fill_rule: vg_lite_fill_t,
+    bbox: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    // build gradient
+    let mut grad: vg_lite_ext_linear_gradient = unsafe { zeroed() };
+    let mut color_ramp: Vec<vg_lite_color_ramp> = lg.stops.iter().map(|x| {
+        x.get_color_ramp(opacity)
+    }).collect();
+    let mut error = unsafe {
+        vg_lite_set_linear_grad(
+            &mut grad,
+            color_ramp.len() as u32,
+            color_ramp.as_mut_ptr(),
+            vg_lite_linear_gradient_parameter {
+                X0: lg.x1 as f32,
+                Y0: lg.y1 as f32,
+                X1: lg.x2 as f32,
+                Y1: lg.y2 as f32
+            },
+            spread_mode(lg.spread_method),
+            0
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe { vg_lite_update_linear_grad(&mut grad) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    } else {
+        let grad_mat = gradient_transform(lg, bbox, m);
+        unsafe { &mut *vg_lite_get_linear_grad_matrix(&mut grad) }.update_transform(&grad_mat);
+        error = unsafe {
+            vg_lite_draw_linear_grad(
+                config.target,
+                p,
+                fill_rule,
+                mr,
+                &mut grad,
+                0,
+                config.blend,
+                vg_lite_filter_VG_LITE_FILTER_BI_LINEAR
+            )
+        };
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            eprintln!("Error at {}:{}", file!(), line!());
+        } else {
+            // the ramp image is used by hardware, wait before release it
+            error = unsafe { vg_lite_finish() };
+        }
+    }
+    let clear = unsafe { vg_lite_clear_linear_grad(&mut grad) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        error
+    } else {
+        clear
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_radial_grad(
+    p: &mut vg_lite_path,
+    rg: &usvg::RadialGradient,
+    opacity: Opacity,
+    fill_rule: vg_lite_fill_t,
+    bbox: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    // build gradient
+    let mut grad: vg_lite_radial_gradient = unsafe { zeroed() };
+    let stops = if rg.stops.len() > VLC_MAX_COLOR_RAMP_STOPS as usize {
+        resample_stops(&rg.stops, VLC_MAX_COLOR_RAMP_STOPS as usize)
+    } else {
+        rg.stops.clone()
+    };
+    let mut color_ramp: Vec<vg_lite_color_ramp> = stops.iter().map(|x| {
+        x.get_color_ramp(opacity)
+    }).collect();
+    let mut error = unsafe {
+        vg_lite_set_radial_grad(
+            &mut grad,
+            color_ramp.len() as u32,
+            color_ramp.as_mut_ptr(),
+            vg_lite_radial_gradient_parameter {
+                cx: rg.cx as f32,
+                cy: rg.cy as f32,
+                r: rg.r.get() as f32,
+                fx: rg.fx as f32,
+                fy: rg.fy as f32
+            },
+            spread_mode(rg.spread_method),
+            0
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+        return error;
+    }
+    error = unsafe { vg_lite_update_radial_grad(&mut grad) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    } else {
+        let grad_mat = gradient_transform(rg, bbox, m);
+        unsafe { &mut *vg_lite_get_radial_grad_matrix(&mut grad) }.update_transform(&grad_mat);
+        error = unsafe {
+            vg_lite_draw_radial_grad(
+                config.target,
+                p,
+                fill_rule,
+                mr,
+                &mut grad,
+                0,
+                config.blend,
+                vg_lite_filter_VG_LITE_FILTER_BI_LINEAR
+            )
+        };
+        if error != vg_lite_error_VG_LITE_SUCCESS {
+            eprintln!("Error at {}:{}", file!(), line!());
+        } else {
+            // the ramp image is used by hardware, wait before release it
+            error = unsafe { vg_lite_finish() };
+        }
+    }
+    let clear = unsafe { vg_lite_clear_radial_grad(&mut grad) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        error
+    } else {
+        clear
+    }
+}
+
+/// Draw radial gradient as a pattern baked over the painted `area`, for
+/// cores without the radial gradient unit
+#[allow(clippy::too_many_arguments)]
+fn draw_radial_bake(
+    p: &mut vg_lite_path,
+    rg: &usvg::RadialGradient,
+    opacity: Opacity,
+    fill_rule: vg_lite_fill_t,
+    bbox: &PathBbox,
+    area: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    // path space to gradient space
+    let inv = if let Some(inv) = invert_transform(&gradient_transform(rg, bbox, &Transform::default())) {
+        inv
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+    // one pixel per target pixel, bounded like the other offscreen images
+    let mut area_mat = *m;
+    area_mat.append(&Transform::new(area.width(), 0., 0., area.height(), area.x(), area.y()));
+    let (sx, sy) = area_mat.get_scale();
+    let width = sx.ceil().clamp(1., MAX_IMAGE_SIZE) as i32;
+    let height = sy.ceil().clamp(1., MAX_IMAGE_SIZE) as i32;
+    let mut image = vg_lite_buffer::default(width, height, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let error = unsafe { vg_lite_allocate(&mut image) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    for y in 0..height as usize {
+        let row = unsafe {
+            slice::from_raw_parts_mut((image.memory as *mut u8).add(y * image.stride as usize), width as usize * 4)
+        };
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let (px, py) = inv.apply(
+                area.x() + (x as f64 + 0.5) / width as f64 * area.width(),
+                area.y() + (y as f64 + 0.5) / height as f64 * area.height()
+            );
+            let t = spread(radial_offset(rg, px, py), rg.spread_method);
+            pixel.copy_from_slice(&sample_stops(&rg.stops, t, opacity));
+        }
+    }
+    let mut image_mat = area_mat;
+    image_mat.scale(1. / width as f64, 1. / height as f64);
+    draw_baked(p, fill_rule, mr, &mut image, &image_mat, config)
+}
+
+/// Gradient offset of point (x, y) in gradient space, it lies on the circle
+/// interpolated from the focal point to the end circle by the offset
+fn radial_offset(rg: &usvg::RadialGradient, x: f64, y: f64) -> f64 {
+    let r = rg.r.get();
+    // a focal point outside of the end circle is moved onto it, as in SVG 1.1
+    let (mut fx, mut fy) = (rg.fx - rg.cx, rg.fy - rg.cy);
+    let focal = (fx * fx + fy * fy).sqrt();
+    if focal > r * 0.999 {
+        fx *= r * 0.999 / focal;
+        fy *= r * 0.999 / focal;
+    }
+    // |d - t * e| = t * r, with d from the focal point to (x, y) and e to the center
+    let (dx, dy) = (x - rg.cx - fx, y - rg.cy - fy);
+    let (ex, ey) = (-fx, -fy);
+    let a = ex * ex + ey * ey - r * r;
+    let b = dx * ex + dy * ey;
+    let c = dx * dx + dy * dy;
+    (b - (b * b - a * c).max(0.).sqrt()) / a
+}
+
+/// Map a gradient offset into `0..1` by the spread method
+fn spread(t: f64, spread_method: SpreadMethod) -> f64 {
+    match spread_method {
+        SpreadMethod::Pad => t.clamp(0., 1.),
+        SpreadMethod::Repeat => t - t.floor(),
+        SpreadMethod::Reflect => 1. - ((t - 2. * (t / 2.).floor()) - 1.).abs(),
+    }
+}
+
+/// Draw a paint baked into `image` by CPU, `image_mat` maps it to the target.
+/// The image is freed.
+fn draw_baked(
+    p: &mut vg_lite_path,
+    fill_rule: vg_lite_fill_t,
+    mr: &mut vg_lite_matrix,
+    image: &mut vg_lite_buffer,
+    image_mat: &Transform,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    let mut error = unsafe {
+        vg_lite_draw_pattern(
+            config.target,
+            p,
+            fill_rule,
+            mr,
+            image,
+            &mut vg_lite_matrix::from_transform(image_mat),
+            config.blend,
+            vg_lite_pattern_mode_VG_LITE_PATTERN_PAD,
+            0,
+            vg_lite_filter_VG_LITE_FILTER_BI_LINEAR
+        )
+    };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        eprintln!("Error at {}:{}", file!(), line!());
+    } else {
+        error = unsafe { vg_lite_finish() };
+    }
+    unsafe { vg_lite_free(image) };
+    error
+}
+
+/// Draw linear gradient as a pattern, the ramp covers the whole path
+/// and spread method is applied while baking.
+#[allow(clippy::too_many_arguments)]
+fn draw_grad_ramp(
+    p: &mut vg_lite_path,
+    lg: &usvg::LinearGradient,
+    opacity: Opacity,
+    fill_rule: vg_lite_fill_t,
+    bbox: &PathBbox,
+    area: &PathBbox,
+    m: &Transform,
+    mr: &mut vg_lite_matrix,
+    config: &VGLiteConfig
+) -> vg_lite_error {
+    // gradient space to path space
+    let grad_mat = gradient_transform(lg, bbox, &Transform::default());
+    let inv = if let Some(inv) = invert_transform(&grad_mat) {
+        inv
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+    let (t0, t1) = if let Some(range) = ramp_range(lg, &inv, area) {
+        range
+    } else {
+        return vg_lite_error_VG_LITE_SUCCESS;
+    };
+
+    let width = VLC_GRADIENT_BUFFER_WIDTH as i32;
+    let mut ramp = vg_lite_buffer::default(width, 1, vg_lite_buffer_format_VG_LITE_RGBA8888);
+    let error = unsafe { vg_lite_allocate(&mut ramp) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    let ramp_memory = unsafe {
+        slice::from_raw_parts_mut(ramp.memory as *mut u8, ramp.stride as usize)
+    };
+    for (i, pixel) in ramp_memory.chunks_exact_mut(4).take(width as usize).enumerate() {
+        let t = t0 + (i as f64 + 0.5) / width as f64 * (t1 - t0);
+        pixel.copy_from_slice(&sample_stops(&lg.stops, spread(t, lg.spread_method), opacity));
+    }
+
+    let mut ramp_mat = *m;
+    ramp_mat.append(&grad_mat);
+    ramp_mat.append(&ramp_transform(lg, t0, t1, width as f64));
+    draw_baked(p, fill_rule, mr, &mut ramp, &ramp_mat, config)
+}
+
+/// Range of the gradient vector the painted `area` projects onto, `inv`
+/// maps path space to gradient space
+fn ramp_range(lg: &usvg::LinearGradient, inv: &Transform, area: &PathBbox) -> Option<(f64, f64)> {
+    let (dx, dy) = (lg.x2 - lg.x1, lg.y2 - lg.y1);
+    let len2 = dx * dx + dy * dy;
+    if len2 == 0. {
+        return None;
+    }
+    let (mut t0, mut t1) = (f64::MAX, f64::MIN);
+    for (x, y) in [
+        (area.left(), area.top()),
+        (area.right(), area.top()),
+        (area.left(), area.bottom()),
+        (area.right(), area.bottom())
+    ] {
+        let (x, y) = inv.apply(x, y);
+        let t = ((x - lg.x1) * dx + (y - lg.y1) * dy) / len2;
+        t0 = t0.min(t);
+        t1 = t1.max(t);
+    }
+    if t1 <= t0 {
+        t1 = t0 + 1.;
+    }
+    Some((t0, t1))
+}
+
+/// Interpolate the color at `t`, in RGBA8888 byte order
+fn sample_stops(stops: &[Stop], t: f64, opacity: Opacity) -> [u8; 4] {
+    let color = |stop: &Stop| {
+        [
+            stop.color.red as f64,
+            stop.color.green as f64,
+            stop.color.blue as f64,
+            (stop.opacity * opacity).get() * 255.
+        ]
+    };
+    let c = match stops.iter().position(|x| x.offset.get() > t) {
+        None => stops.last().map_or([0.; 4], color),
+        Some(0) => color(&stops[0]),
+        Some(i) => {
+            let (a, b) = (&stops[i - 1], &stops[i]);
+            let w = (t - a.offset.get()) / (b.offset.get() - a.offset.get());
+            let (ca, cb) = (color(a), color(b));
+            [
+                ca[0] + (cb[0] - ca[0]) * w,
+                ca[1] + (cb[1] - ca[1]) * w,
+                ca[2] + (cb[2] - ca[2]) * w,
+                ca[3] + (cb[3] - ca[3]) * w
+            ]
+        }
+    };
+    [c[0].round() as u8, c[1].round() as u8, c[2].round() as u8, c[3].round() as u8]
+}
+
+/// Resample stops evenly for hardware stop limit, error of each
+/// channel is bounded by the color change within `1 / (count - 1)`
+fn resample_stops(stops: &[Stop], count: usize) -> Vec<Stop> {
+    (0..count).map(|i| {
+        let t = i as f64 / (count - 1) as f64;
+        let [red, green, blue, alpha] = sample_stops(stops, t, Opacity::ONE);
+        Stop {
+            offset: StopOffset::new_clamped(t),
+            color: usvg::Color::new_rgb(red, green, blue),
+            opacity: Opacity::new_u8(alpha)
+        }
+    }).collect()
+}
+
+/// Ramp image pixel (u, v) to gradient space, u in `0..width` covers `t0..t1`
+/// of the gradient vector and v runs along its normal
+fn ramp_transform(lg: &usvg::LinearGradient, t0: f64, t1: f64, width: f64) -> Transform {
+    let (dx, dy) = (lg.x2 - lg.x1, lg.y2 - lg.y1);
+    let scale = (t1 - t0) / width;
+    Transform::new(
+        dx * scale, dy * scale,
+        -dy * scale, dx * scale,
+        lg.x1 + t0 * dx, lg.y1 + t0 * dy
+    )
+}
+
+/// Gradient space to target, `m` is the path matrix
+fn gradient_transform(gradient: &BaseGradient, bbox: &PathBbox, m: &Transform) -> Transform {
+    let mut grad_mat = *m;
+    if gradient.units == Units::ObjectBoundingBox {
+        grad_mat.append(&Transform::new(bbox.width(), 0., 0., bbox.height(), bbox.x(), bbox.y()));
+    }
+    grad_mat.append(&gradient.transform);
+    grad_mat
+}
+
+fn invert_transform(t: &Transform) -> Option<Transform> {
+    let det = t.a * t.d - t.b * t.c;
+    if det.abs() < f64::EPSILON {
+        return None;
+    }
+    let (a, b, c, d) = (t.d / det, -t.b / det, -t.c / det, t.a / det);
+    Some(Transform::new(a, b, c, d, -(a * t.e + c * t.f), -(b * t.e + d * t.f)))
+}
+
+fn spread_mode(spread_method: SpreadMethod) -> vg_lite_gradient_spreadmode_t {
+    match spread_method {
+        SpreadMethod::Pad => vg_lite_gradient_spreadmode_t_VG_LITE_GRADIENT_SPREAD_PAD,
+        SpreadMethod::Reflect => vg_lite_gradient_spreadmode_t_VG_LITE_GRADIENT_SPREAD_REFLECT,
+        SpreadMethod::Repeat => vg_lite_gradient_spreadmode_t_VG_LITE_GRADIENT_SPREAD_REPEAT,
+    }
+}
+
+fn pack_color(color: usvg::Color, opacity: Opacity) -> vg_lite_color_t {
+    encode_pixel([color.red, color.green, color.blue, opacity.to_u8()], PAINT_FORMAT)
+}
+
+/// Channel order of a packed 32-bit color, from the most significant byte
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ColorLayout {
+    Abgr,
+    Argb,
+    Bgra,
+    Rgba
+}
+
+/// `vg_lite_color_t` of draws, clears and strokes is a pixel of this format
+/// whatever the target, the hardware converts it to the format of the target
+const PAINT_FORMAT: vg_lite_buffer_format = vg_lite_buffer_format_VG_LITE_RGBA8888;
+
+/// Gradient ramps and CLUTs are read by the hardware as pixels of this format
+const TABLE_FORMAT: vg_lite_buffer_format = vg_lite_buffer_format_VG_LITE_BGRA8888;
+
+/// Layout of a pixel of `format` read as a little-endian u32, `None` if the
+/// format isn't 32-bit RGB
+fn pixel_layout(format: vg_lite_buffer_format) -> Option<ColorLayout> {
+    match format {
+        vg_lite_buffer_format_VG_LITE_RGBA8888 | vg_lite_buffer_format_VG_LITE_RGBX8888 => Some(ColorLayout::Abgr),
+        vg_lite_buffer_format_VG_LITE_BGRA8888 | vg_lite_buffer_format_VG_LITE_BGRX8888 => Some(ColorLayout::Argb),
+        vg_lite_buffer_format_VG_LITE_ARGB8888 | vg_lite_buffer_format_VG_LITE_XRGB8888 => Some(ColorLayout::Bgra),
+        vg_lite_buffer_format_VG_LITE_ABGR8888 | vg_lite_buffer_format_VG_LITE_XBGR8888 => Some(ColorLayout::Rgba),
+        _ => None
+    }
+}
+
+/// Whether the alpha channel of a 32-bit `format` is padding
+fn pixel_is_opaque(format: vg_lite_buffer_format) -> bool {
+    matches!(format,
+        vg_lite_buffer_format_VG_LITE_RGBX8888 | vg_lite_buffer_format_VG_LITE_BGRX8888 |
+        vg_lite_buffer_format_VG_LITE_XRGB8888 | vg_lite_buffer_format_VG_LITE_XBGR8888
+    )
+}
+
+/// Bytes of a pixel of `format`, none if it isn't an RGB format
+fn pixel_size(format: vg_lite_buffer_format) -> Option<usize> {
+    match format {
+        vg_lite_buffer_format_VG_LITE_RGB565 | vg_lite_buffer_format_VG_LITE_BGR565 => Some(2),
+        _ => pixel_layout(format).map(|_| 4)
+    }
+}
+
+/// Packs red, green, blue and alpha as a pixel of `format`, read as a
+/// little-endian integer, 0 for formats without RGB channels
+fn encode_pixel(color: [u8; 4], format: vg_lite_buffer_format) -> u32 {
+    let [r, g, b, _] = color.map(|c| c as u32);
+    match format {
+        // the first channel of a 16-bit format takes the least significant bits
+        vg_lite_buffer_format_VG_LITE_RGB565 => (b >> 3) << 11 | (g >> 2) << 5 | r >> 3,
+        vg_lite_buffer_format_VG_LITE_BGR565 => (r >> 3) << 11 | (g >> 2) << 5 | b >> 3,
+        _ => pixel_layout(format).map_or(0, |layout| encode_color(color, layout))
+    }
+}
+
+/// Unpacks a pixel of `format` into red, green, blue and alpha
+fn decode_pixel(pixel: u32, format: vg_lite_buffer_format) -> [u8; 4] {
+    // widen 5 and 6 bits by repeating their top bits
+    let five = |c: u32| ((c & 0x1f) << 3 | (c & 0x1f) >> 2) as u8;
+    let six = |c: u32| ((c & 0x3f) << 2 | (c & 0x3f) >> 4) as u8;
+    match format {
+        vg_lite_buffer_format_VG_LITE_RGB565 => [five(pixel), six(pixel >> 5), five(pixel >> 11), 255],
+        vg_lite_buffer_format_VG_LITE_BGR565 => [five(pixel >> 11), six(pixel >> 5), five(pixel), 255],
+        _ => pixel_layout(format).map_or([0; 4], |layout| decode_color(pixel, layout))
+    }
+}
+
+/// Packs red, green, blue and alpha in `layout`
+fn encode_color([r, g, b, a]: [u8; 4], layout: ColorLayout) -> u32 {
+    let bytes = match layout {
+        ColorLayout::Abgr => [a, b, g, r],
+        ColorLayout::Argb => [a, r, g, b],
+        ColorLayout::Bgra => [b, g, r, a],
+        ColorLayout::Rgba => [r, g, b, a],
+    };
+    u32::from_be_bytes(bytes)
+}
+
+/// Unpacks a color of `layout` into red, green, blue and alpha
+fn decode_color(color: u32, layout: ColorLayout) -> [u8; 4] {
+    let [c0, c1, c2, c3] = color.to_be_bytes();
+    match layout {
+        ColorLayout::Abgr => [c3, c2, c1, c0],
+        ColorLayout::Argb => [c1, c2, c3, c0],
+        ColorLayout::Bgra => [c2, c1, c0, c3],
+        ColorLayout::Rgba => [c0, c1, c2, c3],
+    }
+}
+
 #[allow(unused)]
 fn dfs_dbg(n: &Node) {
     match n.borrow().to_owned() {
@@ -631,17 +3876,44 @@ fn dfs_dbg(n: &Node) {
 }
 
 trait U32Color {
-    fn get_u32(&self) -> u32;
+    fn get_u32(&self, opacity: Opacity) -> u32;
 }
 
 impl U32Color for Stop {
-    fn get_u32(&self) -> u32 {
-        // FIXME: ???
-        ((self.opacity.to_u8() as u32) << 24) |
-        ((self.color.red as u32) << 16) |
-        ((self.color.green as u32) << 8) |
-        ((self.color.blue as u32) << 0)
+    fn get_u32(&self, opacity: Opacity) -> u32 {
+        // the ramp is built from the stops as they are, unlike vg_lite_color_t
+        let alpha = (self.opacity * opacity).to_u8();
+        encode_pixel([self.color.red, self.color.green, self.color.blue, alpha], TABLE_FORMAT)
+    }
+}
+
+trait ColorRamp {
+    fn get_color_ramp(&self, opacity: Opacity) -> vg_lite_color_ramp;
+}
+
+impl ColorRamp for Stop {
+    fn get_color_ramp(&self, opacity: Opacity) -> vg_lite_color_ramp {
+        vg_lite_color_ramp {
+            stop: self.offset.get() as f32,
+            red: self.color.red as f32 / 255.,
+            green: self.color.green as f32 / 255.,
+            blue: self.color.blue as f32 / 255.,
+            alpha: (self.opacity * opacity).get() as f32
+        }
+    }
+}
+
+/// Allocate an offscreen buffer and clear it to transparent
+fn allocate_layer(buffer: &mut vg_lite_buffer) -> vg_lite_error {
+    let error = unsafe { vg_lite_allocate(buffer) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        return error;
+    }
+    let error = unsafe { vg_lite_clear(buffer, null_mut(), encode_pixel([0; 4], PAINT_FORMAT)) };
+    if error != vg_lite_error_VG_LITE_SUCCESS {
+        unsafe { vg_lite_free(buffer) };
     }
+    error
 }
 
 impl vg_lite_buffer {
@@ -678,6 +3950,14 @@ impl vg_lite_buffer {
 }
 
 impl vg_lite_matrix {
+    fn to_transform(self) -> Transform {
+        Transform::new(
+            self.m[0][0] as f64, self.m[1][0] as f64,
+            self.m[0][1] as f64, self.m[1][1] as f64,
+            self.m[0][2] as f64, self.m[1][2] as f64
+        )
+    }
+
     fn from_transform(t: &Transform) -> vg_lite_matrix {
         vg_lite_matrix {
             m: [[t.a as f32, t.c as f32, t.e as f32],
@@ -700,14 +3980,1164 @@ impl vg_lite_matrix {
     }
 }
 
-pub fn convert_rgb24_to_rgb32(src: &[u8], dst: &mut [u8]) {
+/// Writes opaque RGB24 pixels as pixels of `format`, which takes as many
+/// bytes per pixel as `dst` has room for
+pub fn convert_rgb24(src: &[u8], dst: &mut [u8], format: vg_lite_buffer_format) {
+    let size = pixel_size(format).unwrap_or(4);
     let chunks = src.chunks_exact(3);
-    let mut dst_chunks = dst.chunks_exact_mut(4);
+    let mut dst_chunks = dst.chunks_exact_mut(size);
 
     for chunk in chunks {
+        let pixel = encode_pixel([chunk[0], chunk[1], chunk[2], 0xFF], format).to_le_bytes();
         dst_chunks
             .next()
             .unwrap()
-            .copy_from_slice(&[chunk[0], chunk[1], chunk[2], 0xFF]);
+            .copy_from_slice(&pixel[..size]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear_gradients(svg: &[u8]) -> Vec<(std::rc::Rc<usvg::LinearGradient>, PathBbox)> {
+        let tree = Tree::from_data(svg, &usvg::Options::default()).unwrap();
+        tree.root.descendants().filter_map(|node| {
+            if let usvg::NodeKind::Path(ref path) = *node.borrow() {
+                if let Some(usvg::Fill { paint: LinearGradient(ref lg), .. }) = path.fill {
+                    return Some((lg.clone(), paint_bbox(path).unwrap()));
+                }
+            }
+            None
+        }).collect()
+    }
+
+    /// Where the first and the last pixel of the hardware ramp land in path space
+    fn ramp_ends(lg: &usvg::LinearGradient, bbox: &PathBbox) -> ((f64, f64), (f64, f64)) {
+        let mut mat = gradient_transform(lg, bbox, &Transform::default());
+        mat.append(&ramp_transform(lg, 0., 1., 256.));
+        (mat.apply(0., 0.), mat.apply(256., 0.))
+    }
+
+    fn assert_near(a: (f64, f64), b: (f64, f64)) {
+        assert!((a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn linear_gradient_golden() {
+        let gradients = linear_gradients(&std::fs::read("case/gradient.svg").unwrap());
+        let expected = [
+            ((0., 0.), (100., 0.)),
+            ((0., 120.), (100., 220.)),
+            ((100., 0.), (200., 0.)),
+            ((100., 100.), (200., 200.)),
+        ];
+        assert_eq!(gradients.len(), expected.len());
+        for ((lg, bbox), (start, end)) in gradients.iter().zip(expected) {
+            let (s, e) = ramp_ends(lg, bbox);
+            assert_near(s, start);
+            assert_near(e, end);
+        }
+    }
+
+    #[test]
+    fn linear_gradient_bbox_normal() {
+        // iso-lines of a diagonal gradient on a non-square box follow the box diagonal
+        let gradients = linear_gradients(br##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+            <linearGradient id="g" x2="1" y2="1"><stop offset="0"/><stop offset="1"/></linearGradient>
+            <rect x="0" y="0" width="200" height="50" fill="url(#g)"/>
+        </svg>"##);
+        let (lg, bbox) = &gradients[0];
+        let mut mat = gradient_transform(lg, bbox, &Transform::default());
+        mat.append(&ramp_transform(lg, 0., 1., 256.));
+        assert_near(mat.apply(256., 0.), (200., 50.));
+        // one step along the ramp normal stays on the same iso-line
+        let (x, y) = mat.apply(128., 1.);
+        assert_near(((x - 100.) * 50. - (y - 25.) * -200., 0.), (0., 0.));
+    }
+
+    #[test]
+    fn linear_gradient_skewed() {
+        let gradients = linear_gradients(br##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+            <linearGradient id="g" gradientUnits="userSpaceOnUse" x1="10" y1="20" x2="110" y2="20"
+                gradientTransform="skewX(45)"><stop offset="0"/><stop offset="1"/></linearGradient>
+            <rect x="0" y="0" width="200" height="100" fill="url(#g)"/>
+        </svg>"##);
+        let (lg, bbox) = &gradients[0];
+        let (s, e) = ramp_ends(lg, bbox);
+        assert_near(s, (30., 20.));
+        assert_near(e, (130., 20.));
+    }
+
+    /// Nodes are detached when the tree is dropped, keep it with them
+    fn clip_paths(svg: &[u8]) -> (Tree, Vec<(std::rc::Rc<usvg::ClipPath>, Node)>) {
+        let tree = Tree::from_data(svg, &usvg::Options::default()).unwrap();
+        let clips = tree.root.descendants().filter_map(|node| {
+            if let usvg::NodeKind::Group(ref group) = *node.borrow() {
+                return group.clip_path.clone().map(|clip| (clip, node.clone()));
+            }
+            None
+        }).collect();
+        (tree, clips)
+    }
+
+    #[test]
+    fn clip_path_scissor() {
+        let (_tree, clips) = clip_paths(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <clipPath id="r"><rect x="10" y="20" width="30" height="40"/></clipPath>
+            <clipPath id="b" clipPathUnits="objectBoundingBox"><rect width="0.5" height="0.5"/></clipPath>
+            <clipPath id="c"><circle cx="50" cy="50" r="10"/></clipPath>
+            <clipPath id="t"><rect width="10" height="10" transform="rotate(10)"/></clipPath>
+            <g clip-path="url(#r)"><rect width="100" height="100"/></g>
+            <g clip-path="url(#b)"><rect x="20" y="20" width="60" height="40"/></g>
+            <g clip-path="url(#c)"><rect width="100" height="100"/></g>
+            <g clip-path="url(#t)"><rect width="100" height="100"/></g>
+        </svg>"##);
+        let m = Transform::new_scale(2., 2.);
+        let scissors: Vec<_> = clips.iter().map(|(clip, node)| {
+            clip_rect(&clip.root, &clip_transform(clip, node, &m).unwrap())
+        }).collect();
+        assert_eq!(scissors, [
+            Some([20, 40, 80, 120]),
+            Some([40, 40, 100, 80]),
+            None,
+            None
+        ]);
+    }
+
+    #[test]
+    fn object_bbox_excludes_own_transform() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <g transform="translate(50 50)">
+                <rect width="10" height="10"/>
+                <rect x="10" y="10" width="10" height="10" transform="scale(2)"/>
+            </g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let group = tree.root.descendants().find(|node| !node.transform().is_default()).unwrap();
+        let bbox = object_bbox(&group).unwrap();
+        assert_eq!((bbox.x(), bbox.y(), bbox.width(), bbox.height()), (0., 0., 40., 40.));
+    }
+
+    #[test]
+    fn mask_luminance() {
+        assert_eq!(luminance(&[255, 255, 255, 255]), 255);
+        assert_eq!(luminance(&[0, 0, 0, 255]), 0);
+        // premultiplied, half transparent white is half visible
+        assert_eq!(luminance(&[128, 128, 128, 128]), 128);
+        assert_eq!(luminance(&[255, 0, 0, 255]), 54);
+        assert_eq!(luminance(&[0, 255, 0, 255]), 182);
+    }
+
+    #[test]
+    fn blend_modes() {
+        let red = [255, 0, 0, 255];
+        let gray = [128, 128, 128, 255];
+        let transparent = [0, 0, 0, 0];
+        assert_eq!(blend_pixel(BlendMode::Multiply, red, gray), [128, 0, 0, 255]);
+        assert_eq!(blend_pixel(BlendMode::Screen, red, gray), [255, 128, 128, 255]);
+        assert_eq!(blend_pixel(BlendMode::Difference, red, gray), [127, 128, 128, 255]);
+        assert_eq!(blend_pixel(BlendMode::Overlay, red, gray), [255, 1, 1, 255]);
+        // nothing to blend with, the source stays
+        assert_eq!(blend_pixel(BlendMode::ColorBurn, red, transparent), red);
+        // half transparent source over opaque backdrop is normal src-over
+        assert_eq!(blend_pixel(BlendMode::Normal, [128, 0, 0, 128], gray), [192, 64, 64, 255]);
+        // hue of the backdrop with luminosity of the source, clipped into gamut
+        assert_eq!(blend_pixel(BlendMode::Luminosity, gray, red), [255, 74, 74, 255]);
+    }
+
+    #[test]
+    fn box_blur_spreads_evenly() {
+        let (width, height) = (21, 21);
+        let mut pixels = vec![0u8; width * height * 4];
+        let center = (10 * width + 10) * 4;
+        pixels[center..center + 4].copy_from_slice(&[0, 0, 255, 255]);
+        box_blur(&mut pixels, width * 4, [0, 0, width, height], 1., 1.);
+        let alpha = |x: usize, y: usize| pixels[(y * width + x) * 4 + 3];
+        assert!(alpha(10, 10) > alpha(11, 10) && alpha(11, 10) > alpha(12, 10));
+        assert_eq!(alpha(9, 10), alpha(11, 10));
+        assert_eq!(alpha(10, 9), alpha(10, 11));
+        assert_eq!(alpha(0, 0), 0);
+        // blurring keeps the total roughly
+        let total: u32 = pixels.chunks_exact(4).map(|p| p[3] as u32).sum();
+        assert!((200..=310).contains(&total), "{}", total);
+    }
+
+    #[test]
+    fn box_blur_sizes() {
+        assert_eq!(box_sizes(0.3), None);
+        // d = 2, even
+        assert_eq!(box_sizes(1.), Some([(1, 0), (0, 1), (1, 1)]));
+        // d = 3, odd
+        assert_eq!(box_sizes(1.5), Some([(1, 1); 3]));
+    }
+
+    #[test]
+    fn filter_region_default() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <filter id="f"><feGaussianBlur stdDeviation="1"/></filter>
+            <rect x="20" y="20" width="50" height="10" filter="url(#f)"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(|node| {
+            matches!(*node.borrow(), usvg::NodeKind::Group(ref group) if !group.filters.is_empty())
+        }).unwrap();
+        let filter = if let usvg::NodeKind::Group(ref group) = *node.borrow() {
+            group.filters[0].clone()
+        } else {
+            unreachable!()
+        };
+        let bbox = object_bbox(&node).and_then(|bbox| bbox.to_rect());
+        // -10% and 120% of the bounding box, clamped to the target
+        assert_eq!(filter_region(&filter, bbox, &Transform::default(), 100, 100), Some([15, 19, 75, 31]));
+        assert_eq!(filter_region(&filter, bbox, &Transform::default(), 60, 100), Some([15, 19, 60, 31]));
+        assert_eq!(filter_region(&filter, bbox, &Transform::new_translate(-100., 0.), 100, 100), None);
+    }
+
+    #[test]
+    fn color_matrix_kinds() {
+        use usvg::filter::ColorMatrixKind;
+        let identity = color_matrix_values(&ColorMatrixKind::default());
+        let saturate = color_matrix_values(&ColorMatrixKind::Saturate(usvg::PositiveF64::new(1.).unwrap()));
+        let hue = color_matrix_values(&ColorMatrixKind::HueRotate(0.));
+        for i in 0..20 {
+            assert!((saturate[i] - identity[i]).abs() < 1e-9);
+            assert!((hue[i] - identity[i]).abs() < 1e-9);
+        }
+        // rows of the pixel matrix are A, R, G, B
+        let pixel = pixel_matrix(&color_matrix_values(&ColorMatrixKind::LuminanceToAlpha));
+        assert_eq!(pixel[..5], [0., 0.2125, 0.7154, 0.0721, 0.]);
+        assert_eq!(pixel[5..], [0.; 15]);
+    }
+
+    #[test]
+    fn color_matrix_desaturate() {
+        use usvg::filter::ColorMatrixKind;
+        let matrix = color_matrix_values(&ColorMatrixKind::Saturate(usvg::PositiveF64::new(0.).unwrap()));
+        // opaque red and half transparent green, premultiplied
+        let mut pixels = [255, 0, 0, 255, 0, 128, 0, 128];
+        color_matrix_cpu(&mut pixels, 8, [0, 0, 2, 1], &matrix, false);
+        assert_eq!(pixels, [54, 54, 54, 255, 92, 92, 92, 128]);
+        // in linearRGB red is brighter
+        let mut pixels = [255, 0, 0, 255];
+        color_matrix_cpu(&mut pixels, 4, [0, 0, 1, 1], &matrix, true);
+        assert_eq!(pixels, [127, 127, 127, 255]);
+    }
+
+    #[test]
+    fn component_transfer_linear_is_matrix() {
+        use usvg::filter::{ComponentTransfer, Input, TransferFunction};
+        let mut transfer = ComponentTransfer {
+            input: Input::SourceGraphic,
+            func_r: TransferFunction::Linear { slope: 0.5, intercept: 0.25 },
+            func_g: TransferFunction::Identity,
+            func_b: TransferFunction::Identity,
+            func_a: TransferFunction::Identity,
+        };
+        let matrix = component_transfer_matrix(&transfer).unwrap();
+        assert_eq!(matrix[..5], [0.5, 0., 0., 0., 0.25]);
+        assert_eq!(matrix[18], 1.);
+        transfer.func_a = TransferFunction::Table(vec![0., 1.]);
+        assert!(component_transfer_matrix(&transfer).is_none());
+    }
+
+    #[test]
+    fn filter_offset_and_flood() {
+        let (width, height) = (3, 2);
+        let mut src = vec![0u8; width * height * 4];
+        flood_pixels(&mut src, width * 4, [0, 0, 1, 1], usvg::Color::new_rgb(255, 0, 0), Opacity::new_clamped(0.5));
+        assert_eq!(src[..8], [128, 0, 0, 128, 0, 0, 0, 0]);
+        let mut dst = vec![0u8; src.len()];
+        offset_pixels(&mut dst, &src, width * 4, width, height, 2., 1.);
+        assert_eq!(dst[(width + 2) * 4..], [128, 0, 0, 128]);
+        assert_eq!(dst.iter().filter(|&&v| v != 0).count(), 2);
+        // moved out of the buffer
+        offset_pixels(&mut dst, &src, width * 4, width, height, -1., 0.);
+        assert!(dst.iter().all(|&v| v == 0));
+    }
+
+    #[test]
+    fn filter_composite_operators() {
+        use usvg::filter::CompositeOperator;
+        let src = [255, 0, 0, 255, 0, 0, 0, 0];
+        let backdrop = [0, 0, 128, 128, 0, 0, 128, 128];
+        let composite = |operator| {
+            let mut dst = backdrop;
+            composite_pixels(&mut dst, &src, 8, [0, 0, 2, 1], operator);
+            dst
+        };
+        assert_eq!(composite(CompositeOperator::Over), [255, 0, 0, 255, 0, 0, 128, 128]);
+        assert_eq!(composite(CompositeOperator::In), [128, 0, 0, 128, 0, 0, 0, 0]);
+        assert_eq!(composite(CompositeOperator::Out), [127, 0, 0, 127, 0, 0, 0, 0]);
+        assert_eq!(composite(CompositeOperator::Atop), [128, 0, 0, 128, 0, 0, 128, 128]);
+        assert_eq!(composite(CompositeOperator::Xor), [127, 0, 0, 127, 0, 0, 128, 128]);
+        let arithmetic = CompositeOperator::Arithmetic { k1: 0., k2: 0.5, k3: 0.5, k4: 0. };
+        assert_eq!(composite(arithmetic), [128, 0, 64, 192, 0, 0, 64, 64]);
+    }
+
+    #[test]
+    fn filter_morphology() {
+        // a single opaque pixel in a 5x1 line
+        let mut pixels = [0u8; 20];
+        pixels[8..12].copy_from_slice(&[255; 4]);
+        let mut dilated = pixels;
+        morphology_pixels(&mut dilated, 20, [0, 0, 5, 1], 1, 0, true);
+        assert_eq!(dilated.chunks_exact(4).map(|p| p[3]).collect::<Vec<_>>(), [0, 255, 255, 255, 0]);
+        let mut eroded = dilated;
+        morphology_pixels(&mut eroded, 20, [0, 0, 5, 1], 1, 0, false);
+        assert_eq!(eroded, pixels);
+    }
+
+    fn view_box(aspect: &str) -> usvg::ViewBox {
+        let svg = format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50" viewBox="0 0 100 50" preserveAspectRatio="{}"/>"#, aspect);
+        Tree::from_data(svg.as_bytes(), &usvg::Options::default()).unwrap().view_box
+    }
+
+    fn mapped(ts: &Transform) -> ((f64, f64), (f64, f64)) {
+        (ts.apply(0., 0.), ts.apply(100., 50.))
+    }
+
+    #[test]
+    fn fit_document_aspect() {
+        // 100x50 viewBox on a 320x240 panel
+        let meet = view_box("xMidYMid meet");
+        assert_near(mapped(&fit_transform(&meet, 320., 240., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT)).0, (0., 40.));
+        assert_near(mapped(&fit_transform(&meet, 320., 240., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT)).1, (320., 200.));
+        let slice = view_box("xMaxYMax slice");
+        assert_near(mapped(&fit_transform(&slice, 320., 240., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT)).0, (-160., 0.));
+        assert_near(mapped(&fit_transform(&slice, 320., 240., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT)).1, (320., 240.));
+        let none = view_box("none");
+        assert_near(mapped(&fit_transform(&none, 320., 240., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT)).1, (320., 240.));
+    }
+
+    #[test]
+    fn fit_modes() {
+        let vb = view_box("xMidYMid meet");
+        let (tl, br) = mapped(&fit_transform(&vb, 320., 240., SVGLITE_FIT_CONTAIN, SVGLITE_ALIGN_X_MIN_Y_MIN));
+        assert_near(tl, (0., 0.));
+        assert_near(br, (320., 160.));
+        let (tl, br) = mapped(&fit_transform(&vb, 320., 240., SVGLITE_FIT_COVER, SVGLITE_ALIGN_X_MID_Y_MID));
+        assert_near(tl, (-80., 0.));
+        assert_near(br, (400., 240.));
+        let (tl, br) = mapped(&fit_transform(&vb, 320., 240., SVGLITE_FIT_STRETCH, SVGLITE_ALIGN_DOCUMENT));
+        assert_near(tl, (0., 0.));
+        assert_near(br, (320., 240.));
+        let (tl, br) = mapped(&fit_transform(&vb, 320., 240., SVGLITE_FIT_NONE, SVGLITE_ALIGN_X_MAX_Y_MAX));
+        assert_near(tl, (220., 190.));
+        assert_near(br, (320., 240.));
+    }
+
+    #[test]
+    fn dest_rect() {
+        let rect = |x, y, width, height| vg_lite_rectangle { x, y, width, height };
+        assert_eq!(dest_scissor(&rect(10, 20, 32, 32), 320, 240), Some([10, 20, 42, 52]));
+        assert_eq!(dest_scissor(&rect(-8, 220, 32, 32), 320, 240), Some([0, 220, 24, 240]));
+        assert_eq!(dest_scissor(&rect(320, 0, 32, 32), 320, 240), None);
+        assert_eq!(dest_scissor(&rect(0, 0, 0, 32), 320, 240), None);
+
+        let vb = view_box("xMidYMid meet");
+        let m = rect_transform(&vb, &rect(10, 20, 50, 50), SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT, None);
+        assert_near(mapped(&m).0, (10., 32.5));
+        assert_near(mapped(&m).1, (60., 57.5));
+        // rotate a quarter turn about the rectangle's origin
+        let m = rect_transform(&vb, &rect(10, 20, 50, 50), SVGLITE_FIT_STRETCH, SVGLITE_ALIGN_DOCUMENT, Some(&Transform::new(0., 1., -1., 0., 50., 0.)));
+        assert_near(mapped(&m).0, (60., 20.));
+        assert_near(mapped(&m).1, (10., 70.));
+        let matrix = vg_lite_matrix::from_transform(&Transform::new(1., 2., 3., 4., 5., 6.));
+        assert_eq!(matrix.to_transform(), Transform::new(1., 2., 3., 4., 5., 6.));
+    }
+
+    #[test]
+    fn view_spaces() {
+        let vb = view_box("xMidYMid meet");
+        let fit_mat = fit_transform(&vb, 200., 100., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT);
+        let zoom = Transform::new(2., 0., 0., 2., 10., 0.);
+        // pixels of the target
+        let m = view_transform(&fit_mat, &zoom, 0);
+        assert_near(m.apply(100., 50.), (410., 200.));
+        // user units of the viewBox
+        let m = view_transform(&fit_mat, &zoom, SVGLITE_VIEW_VIEWBOX);
+        assert_near(m.apply(100., 50.), (420., 200.));
+    }
+
+    #[test]
+    fn cull_outside() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <rect x="-20" y="10" width="10" height="10"/>
+            <rect x="-20" y="10" width="10" height="10" stroke="black" stroke-width="30"/>
+            <g transform="translate(100 0)"><rect x="10" y="0" width="10" height="10"/><rect x="-10" y="90" width="10" height="10"/></g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let outside: Vec<_> = tree.root.descendants().skip(1)
+            .map(|node| node_extent(&node, &node.abs_transform()).is_outside(&[0., 0., 100., 100.]))
+            .collect();
+        // a group reaches as far as its descendants
+        assert_eq!(outside, [true, false, false, true, false]);
+        // panned by the view matrix
+        let node = tree.root.first_child().unwrap();
+        assert!(!node_extent(&node, &Transform::new_translate(15., 0.)).is_outside(&[0., 0., 100., 100.]));
+        // only the scissor is visible
+        let node = tree.root.last_child().unwrap().last_child().unwrap();
+        assert!(!node_extent(&node, &node.abs_transform()).is_outside(&[80., 80., 100., 100.]));
+        assert!(node_extent(&node, &node.abs_transform()).is_outside(&[0., 0., 50., 50.]));
+    }
+
+    #[test]
+    fn cull_images() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="100" height="100">
+            <image x="120" y="0" width="20" height="20" xlink:href="data:image/svg+xml;utf8,&lt;svg xmlns='http://www.w3.org/2000/svg' width='1' height='1'/&gt;"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(|node| matches!(*node.borrow(), Image(_))).unwrap();
+        assert!(node_extent(&node, &Transform::default()).is_outside(&[0., 0., 100., 100.]));
+        assert!(!node_extent(&node, &Transform::new_translate(-30., 0.)).is_outside(&[0., 0., 100., 100.]));
+        let mut target = vg_lite_buffer::default(100, 100, vg_lite_buffer_format_VG_LITE_RGBA8888);
+        let scissor = VGLiteConfig {
+            target: &mut target,
+            fill_rule: None,
+            blend: vg_lite_blend_VG_LITE_BLEND_SRC_OVER,
+            quality: vg_lite_quality_VG_LITE_HIGH,
+            scissor: Some([-10, 50, 200, 60]),
+            mask: null_mut(),
+            opacity: Opacity::ONE,
+            gif_frame: 0,
+            gifs: std::ptr::null(),
+            cull: true
+        };
+        assert_eq!(visible_rect(&scissor), [0., 50., 100., 60.]);
+    }
+
+    #[test]
+    fn path_bounding_boxes_cover_case_files() {
+        // the box the hardware tessellates in, as dfs builds it
+        fn bounding_boxes(data: &[u8]) -> Vec<(usvg::Path, [f32; 4])> {
+            let tree = Tree::from_data(data, &usvg::Options::default()).unwrap();
+            tree.root.descendants().filter_map(|node| {
+                if let usvg::NodeKind::Path(ref path) = *node.borrow() {
+                    let bbox = stroked_bbox(&paint_bbox(path)?, path.stroke.as_ref());
+                    let mut path_data = build_path_data(&path.data);
+                    let p = new_path(&mut path_data, &bbox, vg_lite_quality_VG_LITE_HIGH);
+                    return Some((path.clone(), p.bounding_box));
+                }
+                None
+            }).collect()
+        }
+        // rects in path space, the node transform of the last one isn't in its box
+        let boxes: Vec<_> = bounding_boxes(&std::fs::read("case/gradient.svg").unwrap())
+            .into_iter().map(|(_, bbox)| bbox).collect();
+        assert_eq!(boxes, [
+            [0., 0., 100., 100.],
+            [0., 120., 100., 220.],
+            [120., 0., 220., 100.],
+            [120., 120., 220., 220.]
+        ]);
+        // miter joins reach out by half the width times the limit, round ones by half the width
+        let boxes: Vec<_> = bounding_boxes(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <rect x="10" y="10" width="10" height="10" stroke="black" stroke-width="4"/>
+            <rect x="10" y="10" width="10" height="10" stroke="black" stroke-width="4" stroke-linejoin="round"/>
+        </svg>"##).into_iter().map(|(_, bbox)| bbox).collect();
+        assert_eq!(boxes, [[2., 2., 28., 28.], [8., 8., 22., 22.]]);
+        // every vertex of the curvy files lands in the box
+        for file in ["fish.svg", "group2.svg", "tiger.svg"] {
+            let boxes = bounding_boxes(&std::fs::read(format!("case/{}", file)).unwrap());
+            assert!(!boxes.is_empty(), "{}", file);
+            for (path, [left, top, right, bottom]) in boxes {
+                for seg in path.data.segments() {
+                    let (x, y) = match seg {
+                        MoveTo { x, y } | LineTo { x, y } | CurveTo { x, y, .. } => (x as f32, y as f32),
+                        ClosePath => continue
+                    };
+                    assert!(
+                        left <= x + 1e-3 && x <= right + 1e-3 && top <= y + 1e-3 && y <= bottom + 1e-3,
+                        "{}: ({}, {}) outside of {:?}", file, x, y, [left, top, right, bottom]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stroked_and_device_bbox() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <path d="M10 10 L50 10" stroke="black" stroke-width="4" stroke-linecap="square" stroke-linejoin="round"/>
+            <path d="M10 10 L50 10 L10 20" stroke="black" stroke-width="4" stroke-miterlimit="10"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let bboxes: Vec<_> = tree.root.descendants().filter_map(|node| {
+            if let usvg::NodeKind::Path(ref path) = *node.borrow() {
+                let bbox = stroked_bbox(&path.data.bbox().unwrap(), path.stroke.as_ref());
+                return Some((bbox.x(), bbox.y(), bbox.width(), bbox.height()));
+            }
+            None
+        }).collect();
+        let r = 2. * std::f64::consts::SQRT_2;
+        assert_eq!(bboxes[0], (10. - r, 10. - r, 40. + 2. * r, 2. * r));
+        assert_eq!(bboxes[1], (-10., -10., 80., 50.));
+        // a horizontal line keeps its box when rotated
+        let line = PathBbox::new(0., 0., 10., 0.).unwrap();
+        let mut m = Transform::new_translate(50., 50.);
+        m.rotate(90.);
+        let bbox = device_bbox(&line, &m).unwrap();
+        assert!((bbox.x() - 50.).abs() < 1e-9 && bbox.width() < 1e-9 && (bbox.height() - 10.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn color_layouts() {
+        let orange = [0xff, 0x80, 0x10, 0xc0];
+        assert_eq!(encode_pixel(orange, PAINT_FORMAT), 0xc01080ff);
+        assert_eq!(encode_pixel(orange, TABLE_FORMAT), 0xc0ff8010);
+        for layout in [ColorLayout::Abgr, ColorLayout::Argb, ColorLayout::Bgra, ColorLayout::Rgba] {
+            assert_eq!(decode_color(encode_color(orange, layout), layout), orange);
+        }
+        // a stop and a solid fill of the same color agree once in their layouts
+        let stop = Stop {
+            offset: StopOffset::ZERO,
+            color: usvg::Color::new_rgb(0xff, 0x80, 0x10),
+            opacity: Opacity::new_clamped(0xc0 as f64 / 255.)
+        };
+        assert_eq!(decode_pixel(stop.get_u32(Opacity::ONE), TABLE_FORMAT), orange);
+        // fill-opacity fades the legacy ramp through its stops
+        assert_eq!(decode_pixel(stop.get_u32(Opacity::new_clamped(0.5)), TABLE_FORMAT), [0xff, 0x80, 0x10, 0x60]);
+        assert_eq!(decode_pixel(pack_color(stop.color, stop.opacity), PAINT_FORMAT), orange);
+    }
+
+    #[test]
+    fn pixel_formats() {
+        let orange = [0xff, 0x80, 0x10, 0xc0];
+        let memory = |format| pixel_layout(format).map(|_| encode_pixel(orange, format).to_le_bytes());
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_RGBA8888), Some([0xff, 0x80, 0x10, 0xc0]));
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_BGRA8888), Some([0x10, 0x80, 0xff, 0xc0]));
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_ARGB8888), Some([0xc0, 0xff, 0x80, 0x10]));
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_ABGR8888), Some([0xc0, 0x10, 0x80, 0xff]));
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_RGBX8888), memory(vg_lite_buffer_format_VG_LITE_RGBA8888));
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_XBGR8888), memory(vg_lite_buffer_format_VG_LITE_ABGR8888));
+        assert_eq!(memory(vg_lite_buffer_format_VG_LITE_RGB565), None);
+        assert!(pixel_is_opaque(vg_lite_buffer_format_VG_LITE_BGRX8888));
+        assert!(!pixel_is_opaque(vg_lite_buffer_format_VG_LITE_BGRA8888));
+    }
+
+    #[test]
+    fn image_opacity_without_layer() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="100" height="100">
+            <image opacity="0.5" image-rendering="optimizeSpeed" width="20" height="20" xlink:href="data:image/svg+xml;utf8,&lt;svg xmlns='http://www.w3.org/2000/svg' width='1' height='1'/&gt;"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(is_single_image).unwrap();
+        let mut target = vg_lite_buffer::default(100, 100, vg_lite_buffer_format_VG_LITE_RGBA8888);
+        let config = VGLiteConfig {
+            target: &mut target,
+            fill_rule: None,
+            blend: vg_lite_blend_VG_LITE_BLEND_NONE,
+            quality: vg_lite_quality_VG_LITE_HIGH,
+            scissor: None,
+            mask: null_mut(),
+            opacity: Opacity::ONE,
+            gif_frame: 0,
+            gifs: std::ptr::null(),
+            cull: true
+        };
+        let config = if let Group(ref group) = *node.borrow() {
+            single_image_config(&node, group, &config).unwrap()
+        } else {
+            panic!("image opacity should be a group");
+        };
+        let image = node.first_child().unwrap();
+        if let Image(ref image) = *image.borrow() {
+            // blended over the target, not replacing it
+            assert_eq!(
+                blit_state(image.rendering_mode, &config),
+                (vg_lite_blend_VG_LITE_BLEND_SRC_OVER, vg_lite_filter_VG_LITE_FILTER_POINT, 0x80)
+            );
+        } else {
+            panic!("group should hold the image");
+        }
+        // a blend mode the hardware lacks still needs a layer
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="100" height="100">
+            <image style="mix-blend-mode: color-burn" width="20" height="20" xlink:href="data:image/svg+xml;utf8,&lt;svg xmlns='http://www.w3.org/2000/svg' width='1' height='1'/&gt;"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(is_single_image).unwrap();
+        if let Group(ref group) = *node.borrow() {
+            assert!(single_image_config(&node, group, &config).is_none());
+        };
+    }
+
+    fn image_view_box(aspect: &str) -> usvg::ViewBox {
+        let svg = format!(r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="200" height="200">
+            <image x="10" y="10" width="100" height="100" preserveAspectRatio="{}" xlink:href="data:image/svg+xml;utf8,&lt;svg xmlns='http://www.w3.org/2000/svg' width='2' height='1'/&gt;"/>
+        </svg>"##, aspect);
+        let tree = Tree::from_data(svg.as_bytes(), &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(|node| matches!(*node.borrow(), Image(_))).unwrap();
+        if let Image(ref image) = *node.borrow() {
+            return image.view_box;
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn image_placement() {
+        // a 200x100 image in a 100x100 box
+        let m = image_transform(&image_view_box("xMidYMid meet"), 200., 100.);
+        assert_near(m.apply(0., 0.), (10., 35.));
+        assert_near(m.apply(200., 100.), (110., 85.));
+        let m = image_transform(&image_view_box("xMaxYMax slice"), 200., 100.);
+        assert_near(m.apply(0., 0.), (-90., 10.));
+        assert_near(m.apply(200., 100.), (110., 110.));
+        let m = image_transform(&image_view_box("none"), 200., 100.);
+        assert_near(m.apply(200., 100.), (110., 110.));
+        // the height scales by the height
+        let m = image_transform(&image_view_box("none"), 100., 400.);
+        assert_near(m.apply(100., 400.), (110., 110.));
+    }
+
+    #[test]
+    fn image_slice_clip() {
+        let view_box = image_view_box("xMidYMid slice");
+        let clip = image_clip(view_box.rect);
+        assert_eq!(clip_rect(&clip.root, &Transform::new_scale(2., 2.)), Some([20, 20, 220, 220]));
+    }
+
+    #[test]
+    fn gif_frames() {
+        // red, blue and a transparent index
+        let palette = [255, 0, 0, 0, 0, 255, 0, 0, 0];
+        let mut data = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut data, 2, 2, &palette).unwrap();
+            encoder.write_frame(&gif::Frame {
+                width: 2,
+                height: 2,
+                buffer: std::borrow::Cow::Borrowed(&[0, 0, 0, 2]),
+                transparent: Some(2),
+                ..gif::Frame::default()
+            }).unwrap();
+            encoder.write_frame(&gif::Frame {
+                left: 1,
+                width: 1,
+                height: 2,
+                buffer: std::borrow::Cow::Borrowed(&[1, 2]),
+                transparent: Some(2),
+                dispose: gif::DisposalMethod::Background,
+                ..gif::Frame::default()
+            }).unwrap();
+        }
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let clear = [0; 4];
+        let mut animation = GifAnimation::new(Arc::new(data.clone())).unwrap();
+        assert_eq!((animation.width, animation.height), (2, 2));
+        let first = [red, red, red, clear].concat();
+        let second = [red, blue, red, clear].concat();
+        assert_eq!(animation.frame(0).unwrap(), first);
+        assert_eq!(animation.frame(1).unwrap(), second);
+        assert_eq!(animation.count, None);
+        // steps past the last frame loop the animation
+        assert_eq!(animation.frame(2).unwrap(), first);
+        assert_eq!(animation.count, Some(2));
+        assert_eq!(animation.frame(3).unwrap(), second);
+        // the same frame again isn't decoded again
+        assert_eq!(animation.decoded, 2);
+        assert_eq!(animation.frame(1).unwrap(), second);
+        assert_eq!(animation.decoded, 2);
+        let data = Arc::new(data[..data.len() / 2].to_vec());
+        assert_eq!(GifAnimation::new(data).and_then(|mut animation| animation.frame(0).map(<[u8]>::to_vec)), None);
+    }
+
+    #[test]
+    fn gif_cache_evicts_errors() {
+        let palette = [255, 0, 0, 0, 0, 255];
+        let frame = |buffer: &'static [u8]| gif::Frame {
+            width: 2,
+            height: 1,
+            buffer: std::borrow::Cow::Borrowed(buffer),
+            ..gif::Frame::default()
+        };
+        let encode = |frames: &[gif::Frame]| {
+            let mut data = Vec::new();
+            let mut encoder = gif::Encoder::new(&mut data, 2, 1, &palette).unwrap();
+            for frame in frames {
+                encoder.write_frame(frame).unwrap();
+            }
+            drop(encoder);
+            data
+        };
+        // cut into the second frame, the first still decodes
+        let first = encode(&[frame(&[0, 1])]);
+        let data = encode(&[frame(&[0, 1]), frame(&[1, 0])]);
+        let broken = Arc::new(data[..first.len() + 4].to_vec());
+        let whole = Arc::new(data);
+        let mut gifs = HashMap::new();
+        let pixels = |gifs: &mut HashMap<_, _>, gif, index| gif_frame(gifs, gif, index).map(|(_, _, pixels)| pixels.to_vec());
+        assert_eq!(pixels(&mut gifs, &broken, 0), Some([255, 0, 0, 255, 0, 0, 255, 255].to_vec()));
+        assert!(gifs.contains_key(&Arc::as_ptr(&broken)));
+        assert_eq!(pixels(&mut gifs, &broken, 1), None);
+        assert!(!gifs.contains_key(&Arc::as_ptr(&broken)));
+        assert_eq!(pixels(&mut gifs, &whole, 1), Some([0, 0, 255, 255, 255, 0, 0, 255].to_vec()));
+        assert_eq!(gif_frame(&mut gifs, &whole, 1).map(|(width, height, _)| (width, height)), Some((2, 1)));
+        assert_eq!(gifs.len(), 1);
+    }
+
+    #[test]
+    fn png_palette() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let clut = clut_colors(&palette, Some(&[0x80]), 4);
+        assert_eq!(clut.len(), 4);
+        assert_eq!(decode_pixel(clut[0], TABLE_FORMAT), [255, 0, 0, 0x80]);
+        assert_eq!(decode_pixel(clut[2], TABLE_FORMAT), [0, 0, 255, 255]);
+        assert_eq!(clut[3], 0);
+        assert_eq!([1, 2, 4, 8].map(index_format), [
+            vg_lite_buffer_format_VG_LITE_INDEX_1,
+            vg_lite_buffer_format_VG_LITE_INDEX_2,
+            vg_lite_buffer_format_VG_LITE_INDEX_4,
+            vg_lite_buffer_format_VG_LITE_INDEX_8
+        ]);
+    }
+
+    #[test]
+    fn png_index_unpacking() {
+        // indices 1, 2, 3, 0 and 5
+        let row4 = [0x12, 0x30, 0x50];
+        assert_eq!((0..5).map(|x| png_index(&row4, 4, x)).collect::<Vec<_>>(), [1, 2, 3, 0, 5]);
+        let row2 = [0b01_10_11_00];
+        assert_eq!((0..4).map(|x| png_index(&row2, 2, x)).collect::<Vec<_>>(), [1, 2, 3, 0]);
+        let row1 = [0b1000_0001];
+        assert_eq!((0..8).map(|x| png_index(&row1, 1, x)).collect::<Vec<_>>(), [1, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn index_reversal() {
+        // pixel x of a little endian row is read from the least significant bits
+        let little_index = |row: &[u8], bits: usize, x: usize| {
+            let per_byte = 8 / bits;
+            (row[x / per_byte] >> (bits * (x % per_byte))) & ((1u16 << bits) - 1) as u8
+        };
+        let rows: [(&[u8], usize, usize); 4] = [
+            (&[0x12, 0x30, 0x50], 4, 5),
+            (&[0b01_10_11_00], 2, 4),
+            (&[0b1000_0011], 1, 8),
+            (&[7, 200], 8, 2)
+        ];
+        for (row, bits, width) in rows {
+            let mut reversed = vec![0; row.len()];
+            reverse_indices(row, &mut reversed, bits);
+            let png: Vec<_> = (0..width).map(|x| png_index(row, bits, x)).collect();
+            let little: Vec<_> = (0..width).map(|x| little_index(&reversed, bits, x)).collect();
+            assert_eq!(png, little);
+        }
+        let mut reversed = [0; 2];
+        reverse_indices(&[0x12, 0x30], &mut reversed, 4);
+        assert_eq!(reversed, [0x21, 0x03]);
+    }
+
+    #[test]
+    fn linear_gradient_transformed_path() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+            <linearGradient id="lg"><stop offset="0"/><stop offset="1" stop-color="red"/></linearGradient>
+            <g transform="translate(100 0) scale(2)">
+                <rect x="0" y="0" width="10" height="10" fill="url(#lg)" stroke="black" stroke-width="4"/>
+            </g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(|node| matches!(*node.borrow(), Path(_))).unwrap();
+        let (lg, bbox) = if let Path(ref path) = *node.borrow() {
+            if let Some(usvg::Fill { paint: LinearGradient(ref lg), .. }) = path.fill {
+                (lg.clone(), paint_bbox(path).unwrap())
+            } else {
+                unreachable!()
+            }
+        } else {
+            unreachable!()
+        };
+        // the matrix dfs hands to the paint, the stroke doesn't widen the gradient
+        let mut mat = gradient_transform(&lg, &bbox, &node.abs_transform());
+        mat.append(&ramp_transform(&lg, 0., 1., 256.));
+        assert_near(mat.apply(0., 0.), (100., 0.));
+        assert_near(mat.apply(256., 0.), (120., 0.));
+    }
+
+    fn stroke_styles(svg: &[u8]) -> Vec<StrokeStyle> {
+        let tree = Tree::from_data(svg, &usvg::Options::default()).unwrap();
+        tree.root.descendants().filter_map(|node| {
+            if let usvg::NodeKind::Path(ref path) = *node.borrow() {
+                return path.stroke.as_ref().map(stroke_style);
+            }
+            None
+        }).collect()
+    }
+
+    #[test]
+    fn stroke_style_mapping() {
+        let styles = stroke_styles(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <path d="M0 0 L10 10" stroke="#ff8000"/>
+            <path d="M0 0 L10 10" stroke="#ff8000" stroke-opacity="0.5" stroke-width="3"
+                stroke-linecap="round" stroke-linejoin="round" stroke-miterlimit="8"/>
+            <path d="M0 0 L10 10" stroke="#ff8000" stroke-linecap="square" stroke-linejoin="bevel"/>
+        </svg>"##);
+        assert_eq!(styles[0], StrokeStyle {
+            cap: vg_lite_cap_style_VG_LITE_CAP_BUTT,
+            join: vg_lite_join_style_VG_LITE_JOIN_MITER,
+            width: 1.,
+            miter_limit: 4.,
+            dash_pattern: vec![],
+            dash_phase: 0.,
+            color: pack_color(usvg::Color::new_rgb(0xff, 0x80, 0), Opacity::ONE)
+        });
+        assert_eq!(styles[1], StrokeStyle {
+            cap: vg_lite_cap_style_VG_LITE_CAP_ROUND,
+            join: vg_lite_join_style_VG_LITE_JOIN_ROUND,
+            width: 3.,
+            miter_limit: 8.,
+            dash_pattern: vec![],
+            dash_phase: 0.,
+            color: pack_color(usvg::Color::new_rgb(0xff, 0x80, 0), Opacity::new_clamped(0.5))
+        });
+        assert_eq!(styles[1].color >> 24, 0x80);
+        assert_eq!((styles[2].cap, styles[2].join), (vg_lite_cap_style_VG_LITE_CAP_SQUARE, vg_lite_join_style_VG_LITE_JOIN_BEVEL));
+    }
+
+    #[test]
+    fn stroke_dashes() {
+        let styles = stroke_styles(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <path d="M0 0 L10 10" stroke="black" stroke-dasharray="4 2" stroke-dashoffset="3"/>
+            <path d="M0 0 L10 10" stroke="black" stroke-dasharray="5 3 1"/>
+            <path d="M0 0 L10 10" stroke="black" stroke-dasharray="0 0"/>
+            <path d="M0 0 L10 10" stroke="black" stroke-dasharray="4 2" stroke-dashoffset="-1"/>
+        </svg>"##);
+        assert_eq!((styles[0].dash_pattern.as_slice(), styles[0].dash_phase), (&[4., 2.][..], 3.));
+        // an odd count is repeated to an even one
+        assert_eq!(styles[1].dash_pattern, [5., 3., 1., 5., 3., 1.]);
+        // all zero draws a solid line
+        assert!(styles[2].dash_pattern.is_empty());
+        assert_eq!(styles[3].dash_phase, -1.);
+    }
+
+    #[test]
+    fn radial_gradient_geometry() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+            <radialGradient id="rg" fx="0.25"><stop offset="0"/><stop offset="1" stop-color="red"/></radialGradient>
+            <rect x="10" y="20" width="100" height="50" fill="url(#rg)"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let (rg, bbox) = tree.root.descendants().find_map(|node| {
+            if let Path(ref path) = *node.borrow() {
+                if let Some(usvg::Fill { paint: RadialGradient(ref rg), .. }) = path.fill {
+                    return Some((rg.clone(), paint_bbox(path).unwrap()));
+                }
+            }
+            None
+        }).unwrap();
+        // objectBoundingBox units stretch the circle over the box
+        let mat = gradient_transform(&rg, &bbox, &Transform::default());
+        assert_near(mat.apply(rg.cx, rg.cy), (60., 45.));
+        assert_near(mat.apply(rg.fx, rg.fy), (35., 45.));
+        assert_near(mat.apply(rg.cx + rg.r.get(), rg.cy), (110., 45.));
+        // offsets grow from the focal point to the end circle
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(near(radial_offset(&rg, 0.25, 0.5), 0.));
+        assert!(near(radial_offset(&rg, 0.125, 0.5), 0.5));
+        assert!(near(radial_offset(&rg, 0.625, 0.5), 0.5));
+        assert!(near(radial_offset(&rg, 0., 0.5), 1.));
+        assert!(near(radial_offset(&rg, 1., 0.5), 1.));
+        assert!(near(radial_offset(&rg, 1.75, 0.5), 2.));
+        assert_eq!(spread(1.25, SpreadMethod::Pad), 1.);
+        assert_eq!(spread(1.25, SpreadMethod::Repeat), 0.25);
+        assert_eq!(spread(1.25, SpreadMethod::Reflect), 0.75);
+    }
+
+    #[test]
+    fn pattern_tiles() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="200">
+            <pattern id="user" patternUnits="userSpaceOnUse" x="5" y="5" width="10" height="20"><rect width="1" height="1"/></pattern>
+            <pattern id="bbox" width="0.5" height="0.25"><rect width="1" height="1"/></pattern>
+            <pattern id="content" patternContentUnits="objectBoundingBox" width="0.5" height="0.5"><rect width="1" height="1"/></pattern>
+            <pattern id="view" patternUnits="userSpaceOnUse" width="10" height="10" viewBox="0 0 2 2"><rect width="1" height="1"/></pattern>
+            <pattern id="transform" patternUnits="userSpaceOnUse" width="10" height="10" patternTransform="scale(3 2)"><rect width="1" height="1"/></pattern>
+            <rect x="20" y="40" width="40" height="80" fill="url(#user)"/>
+            <rect x="20" y="40" width="40" height="80" fill="url(#bbox)"/>
+            <rect x="20" y="40" width="40" height="80" fill="url(#content)"/>
+            <rect x="20" y="40" width="40" height="80" fill="url(#view)"/>
+            <rect x="20" y="40" width="40" height="80" fill="url(#transform)"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let m = Transform::new_scale(2., 2.);
+        let tiles: Vec<_> = tree.root.descendants().filter_map(|node| {
+            if let Path(ref path) = *node.borrow() {
+                if let Some(usvg::Fill { paint: Pattern(ref pattern), .. }) = path.fill {
+                    return pattern_tile(pattern, &paint_bbox(path).unwrap(), &m);
+                }
+            }
+            None
+        }).collect();
+        // userSpaceOnUse, the tile is in target pixels and lands at the pattern origin
+        let (width, height, content, place) = tiles[0];
+        assert_eq!((width, height), (20, 40));
+        assert_near(content.apply(1., 1.), (2., 2.));
+        assert_near(place.apply(0., 0.), (10., 10.));
+        assert_near(place.apply(20., 40.), (30., 50.));
+        // objectBoundingBox units, a fraction of the path box
+        let (width, height, content, place) = tiles[1];
+        assert_eq!((width, height), (40, 40));
+        assert_near(content.apply(1., 1.), (2., 2.));
+        assert_near(place.apply(0., 0.), (40., 80.));
+        // content in objectBoundingBox units is scaled by the path box
+        let (width, height, content, _) = tiles[2];
+        assert_eq!((width, height), (40, 80));
+        assert_near(content.apply(1., 1.), (80., 160.));
+        // viewBox maps onto the tile
+        let (width, height, content, _) = tiles[3];
+        assert_eq!((width, height), (20, 20));
+        assert_near(content.apply(2., 2.), (20., 20.));
+        // patternTransform scales the tile resolution and its placement
+        let (width, height, content, place) = tiles[4];
+        assert_eq!((width, height), (60, 40));
+        assert_near(content.apply(10., 10.), (60., 40.));
+        assert_near(place.apply(60., 40.), (60., 40.));
+    }
+
+    #[test]
+    fn ramp_range_covers_stroke() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+            <linearGradient id="lg" spreadMethod="repeat" x2="0.5"><stop offset="0"/><stop offset="1" stop-color="red"/></linearGradient>
+            <rect x="0" y="0" width="100" height="10" fill="none" stroke="url(#lg)" stroke-width="20" stroke-linejoin="round"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.root.descendants().find(|node| matches!(*node.borrow(), Path(_))).unwrap();
+        if let Path(ref path) = *node.borrow() {
+            let lg = if let Some(usvg::Stroke { paint: LinearGradient(ref lg), .. }) = path.stroke {
+                lg.clone()
+            } else {
+                unreachable!()
+            };
+            let bbox = paint_bbox(path).unwrap();
+            let inv = invert_transform(&gradient_transform(&lg, &bbox, &Transform::default())).unwrap();
+            let (t0, t1) = ramp_range(&lg, &inv, &bbox).unwrap();
+            assert!((t0 - 0.).abs() < 1e-9 && (t1 - 2.).abs() < 1e-9);
+            // the stroke reaches 10 past each side, a fifth of a cycle
+            let area = stroked_bbox(&bbox, path.stroke.as_ref());
+            let (t0, t1) = ramp_range(&lg, &inv, &area).unwrap();
+            assert!((t0 + 0.2).abs() < 1e-9 && (t1 - 2.2).abs() < 1e-9, "{} {}", t0, t1);
+        };
+    }
+
+    #[test]
+    fn resample_many_stops() {
+        let color = |t: f64| [255. * t, 128. + 127. * (6. * t).sin(), 255. * (1. - t), 255. * (0.5 + 0.5 * (3. * t).cos())];
+        let stops: Vec<_> = (0..40).map(|i| {
+            let t = i as f64 / 39.;
+            let [r, g, b, a] = color(t);
+            Stop {
+                offset: StopOffset::new_clamped(t),
+                color: usvg::Color::new_rgb(r.round() as u8, g.round() as u8, b.round() as u8),
+                opacity: Opacity::new_clamped(a.round() / 255.)
+            }
+        }).collect();
+        let count = VLC_MAX_GRADIENT_STOPS as usize;
+        let resampled = resample_stops(&stops, count);
+        assert_eq!(resampled.len(), count);
+        assert_eq!(resampled[0].offset.get(), 0.);
+        assert_eq!(resampled[count - 1].offset.get(), 1.);
+        // each channel stays within the change of the original over one resampled step
+        let step = 1. / (count - 1) as f64;
+        for i in 0..=1000 {
+            let t = i as f64 / 1000.;
+            let exact = sample_stops(&stops, t, Opacity::ONE);
+            let approx = sample_stops(&resampled, t, Opacity::ONE);
+            for c in 0..4 {
+                let bound = (0..=20).map(|j| {
+                    let u = (t - step + 2. * step * j as f64 / 20.).clamp(0., 1.);
+                    (sample_stops(&stops, u, Opacity::ONE)[c] as i32 - exact[c] as i32).abs()
+                }).max().unwrap();
+                assert!(
+                    (exact[c] as i32 - approx[c] as i32).abs() <= bound + 1,
+                    "t {} channel {}: {} vs {}, bound {}", t, c, exact[c], approx[c], bound
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn group_layers() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <g id="faded" opacity="0.5"><rect width="10" height="10"/><rect x="5" width="10" height="10"/></g>
+            <g id="opaque"><rect width="10" height="10"/><rect x="5" width="10" height="10"/></g>
+            <g id="multiply" style="mix-blend-mode: multiply"><rect width="10" height="10"/></g>
+            <g id="overlap" style="mix-blend-mode: multiply"><rect width="10" height="10"/><rect x="5" width="10" height="10"/></g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let mut target = vg_lite_buffer::default(100, 100, vg_lite_buffer_format_VG_LITE_RGBA8888);
+        let config = VGLiteConfig {
+            target: &mut target,
+            fill_rule: None,
+            blend: vg_lite_blend_VG_LITE_BLEND_SRC_OVER,
+            quality: vg_lite_quality_VG_LITE_HIGH,
+            scissor: None,
+            mask: null_mut(),
+            opacity: Opacity::ONE,
+            gif_frame: 0,
+            gifs: std::ptr::null(),
+            cull: true
+        };
+        let direct = |id: &str| {
+            let node = tree.node_by_id(id).unwrap();
+            let group = if let Group(ref group) = *node.borrow() {
+                group.clone()
+            } else {
+                unreachable!()
+            };
+            direct_config(&node, &group, &config).map(|config| config.blend)
+        };
+        // overlapping children are faded as a whole
+        assert_eq!(direct("faded"), None);
+        assert_eq!(direct("opaque"), Some(vg_lite_blend_VG_LITE_BLEND_SRC_OVER));
+        assert_eq!(direct("multiply"), Some(vg_lite_blend_VG_LITE_BLEND_MULTIPLY));
+        assert_eq!(direct("overlap"), None);
+    }
+
+    #[test]
+    fn masked_group_layer() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <mask id="m"><rect width="100" height="100" fill="white" fill-opacity="0.5"/></mask>
+            <g id="g" opacity="0.5" mask="url(#m)"><rect width="10" height="10"/><rect x="5" width="10" height="10"/></g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let node = tree.node_by_id("g").unwrap();
+        let group = if let Group(ref group) = *node.borrow() {
+            group.clone()
+        } else {
+            unreachable!()
+        };
+        assert!(group.mask.is_some());
+        let mut target = vg_lite_buffer::default(100, 100, vg_lite_buffer_format_VG_LITE_RGBA8888);
+        let mut mask = vg_lite_buffer::default(100, 100, vg_lite_buffer_format_VG_LITE_A8);
+        let mut layer = vg_lite_buffer::default(100, 100, vg_lite_buffer_format_VG_LITE_RGBA8888);
+        // the config draw_masked hands to the group
+        let config = VGLiteConfig {
+            target: &mut target,
+            fill_rule: None,
+            blend: vg_lite_blend_VG_LITE_BLEND_SRC_OVER,
+            quality: vg_lite_quality_VG_LITE_HIGH,
+            scissor: Some([0, 0, 50, 50]),
+            mask: &mut mask,
+            opacity: Opacity::ONE,
+            gif_frame: 0,
+            gifs: std::ptr::null(),
+            cull: true
+        };
+        assert!(direct_config(&node, &group, &config).is_none());
+        // the children are drawn unmasked, only the composite scales by the mask
+        let children = offscreen_config(&mut layer, &config);
+        assert!(children.mask.is_null());
+        assert_eq!(children.scissor, None);
+        assert_eq!(children.target, &mut layer as *mut _);
+    }
+
+    #[test]
+    fn path_fill_rules() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <path d="M0 0 H10 V10 Z" fill-rule="evenodd"/>
+            <path d="M0 0 H10 V10 Z" fill-rule="nonzero"/>
+            <path d="M0 0 H10 V10 Z"/>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let fills: Vec<_> = tree.root.descendants().filter_map(|node| {
+            if let Path(ref path) = *node.borrow() {
+                return path.fill.clone();
+            }
+            None
+        }).collect();
+        let rules = |fill_rule: vg_lite_fill_t| -> Vec<_> {
+            fills.iter().map(|fill| path_fill_rule(fill, fill_rule_override(fill_rule))).collect()
+        };
+        let (even_odd, non_zero) = (vg_lite_fill_VG_LITE_FILL_EVEN_ODD, vg_lite_fill_VG_LITE_FILL_NON_ZERO);
+        // SVGLITE_FILL_RULE_AUTO keeps the rule of each path
+        assert_eq!(rules(0), [even_odd, non_zero, non_zero]);
+        assert_eq!(rules(even_odd), [even_odd, even_odd, even_odd]);
+        assert_eq!(rules(non_zero), [non_zero, non_zero, non_zero]);
+    }
+
+    #[test]
+    fn cull_groups() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <filter id="blur" x="-5" y="0" width="11" height="1"><feGaussianBlur stdDeviation="5"/></filter>
+            <filter id="far" x="20" y="0" width="1" height="1"><feGaussianBlur stdDeviation="5"/></filter>
+            <g id="off"><rect x="120" y="0" width="10" height="10"/><rect x="110" y="50" width="10" height="10" stroke="black"/></g>
+            <g id="edge"><rect x="90" y="0" width="20" height="10"/></g>
+            <g id="inside"><g><rect x="10" y="10" width="10" height="10"/></g></g>
+            <g id="filtered" filter="url(#blur)"><rect x="120" y="0" width="10" height="10"/></g>
+            <g id="away" filter="url(#far)"><rect x="10" y="10" width="10" height="10"/></g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let rect = [0., 0., 100., 100.];
+        let group = |id: &str| tree.root.descendants().find(|node| &*node.id() == id).unwrap();
+        let extent = |id: &str| node_extent(&group(id), &Transform::default());
+        let cull = |id: &str| match *group(id).borrow() {
+            Group(ref group) => cull_children(group, &extent(id), &rect),
+            _ => unreachable!()
+        };
+        assert!(extent("off").is_outside(&rect));
+        // straddles the edge, each child is tested again
+        assert!(!extent("edge").is_outside(&rect));
+        assert!(cull("edge"));
+        // fully visible, nothing below is tested
+        assert!(extent("inside").is_inside(&rect));
+        assert!(!cull("inside"));
+        // the blur region reaches back into view, whatever its children
+        assert!(!extent("filtered").is_outside(&rect));
+        assert!(!cull("filtered"));
+        // the filter region is out of view, whatever its children
+        assert!(extent("away").is_outside(&rect));
+        // the view matrix moves the whole group into view
+        assert!(!node_extent(&group("off"), &Transform::new_translate(-30., 0.)).is_outside(&rect));
+    }
+
+    #[test]
+    fn cull_views() {
+        let tree = Tree::from_data(br##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 200 200">
+            <rect id="corner" x="0" y="0" width="20" height="20"/>
+            <rect id="center" x="90" y="90" width="20" height="20"/>
+            <g id="far"><rect x="120" y="120" width="20" height="20"/><rect x="140" y="100" width="20" height="20"/></g>
+        </svg>"##, &usvg::Options::default()).unwrap();
+        let rect = [0., 0., 100., 100.];
+        let fit_mat = fit_transform(&tree.view_box, 100., 100., SVGLITE_FIT_DOCUMENT, SVGLITE_ALIGN_DOCUMENT);
+        let outside = |view: &Transform, space: u32| -> Vec<_> {
+            let m = view_transform(&fit_mat, view, space);
+            ["corner", "center", "far"].iter().map(|id| {
+                let node = tree.root.descendants().find(|node| &*node.id() == *id).unwrap();
+                let mut m = m;
+                m.append(&node.abs_transform());
+                node_extent(&node, &m).is_outside(&rect)
+            }).collect()
+        };
+        assert_eq!(outside(&Transform::default(), 0), [false, false, false]);
+        // turned about the middle of the target, the corner swings out of view
+        let mut rotate = Transform::default();
+        rotate.rotate_at(45., 50., 50.);
+        let rotate = vg_lite_matrix::from_transform(&rotate).to_transform();
+        assert_eq!(outside(&rotate, 0), [true, false, false]);
+        // zoomed into the top left of the viewBox
+        let zoom = Transform::new_scale(4., 4.);
+        assert_eq!(outside(&zoom, SVGLITE_VIEW_VIEWBOX), [false, true, true]);
+        // both about the middle, what is near it stays in view
+        let mut both = rotate;
+        both.translate(50., 50.);
+        both.scale(2., 2.);
+        both.translate(-50., -50.);
+        assert_eq!(outside(&both, 0), [true, false, false]);
+    }
+
+    #[test]
+    fn target_formats() {
+        let orange = [0xff, 0x80, 0x10, 0xff];
+        let rgb565 = vg_lite_buffer_format_VG_LITE_RGB565;
+        let bgr565 = vg_lite_buffer_format_VG_LITE_BGR565;
+        assert_eq!(encode_pixel(orange, rgb565), 0x141f);
+        assert_eq!(encode_pixel(orange, bgr565), 0xfc02);
+        assert_eq!(decode_pixel(0x141f, rgb565), [0xff, 0x82, 0x10, 0xff]);
+        assert_eq!(decode_pixel(encode_pixel([0, 0, 0xff, 0xff], bgr565), bgr565), [0, 0, 0xff, 0xff]);
+        assert_eq!(encode_pixel(orange, vg_lite_buffer_format_VG_LITE_L8), 0);
+        // decoded RGB lands in the byte order of the buffer it's written to
+        let rgb = [0xff, 0x80, 0x10, 0x00, 0x00, 0xff];
+        let convert = |format| {
+            let mut dst = vec![0u8; 2 * pixel_size(format).unwrap()];
+            convert_rgb24(&rgb, &mut dst, format);
+            dst
+        };
+        assert_eq!(convert(vg_lite_buffer_format_VG_LITE_BGRA8888), [0x10, 0x80, 0xff, 0xff, 0xff, 0x00, 0x00, 0xff]);
+        assert_eq!(convert(vg_lite_buffer_format_VG_LITE_RGBA8888), [0xff, 0x80, 0x10, 0xff, 0x00, 0x00, 0xff, 0xff]);
+        assert_eq!(convert(rgb565), [0x1f, 0x14, 0x00, 0xf8]);
+        // paint colors and tables keep their own formats whatever the target
+        let stop = Stop {
+            offset: StopOffset::ZERO,
+            color: usvg::Color::new_rgb(0xff, 0x80, 0x10),
+            opacity: Opacity::ONE
+        };
+        assert_eq!(pack_color(stop.color, stop.opacity), encode_pixel(orange, PAINT_FORMAT));
+        assert_eq!(stop.get_u32(Opacity::ONE), encode_pixel(orange, TABLE_FORMAT));
+        assert_eq!(clut_colors(&[0xff, 0x80, 0x10], None, 1), [encode_pixel(orange, TABLE_FORMAT)]);
     }
 }
@@ -145,7 +145,7 @@ int main(int argc, char* argv[]) {
         printf("vg_lite_clear() error: %d", error);
         return -1;
     }
-    error = svglite_render(fb, svg, VG_LITE_FILL_NON_ZERO, VG_LITE_BLEND_NONE, VG_LITE_HIGH, fonts);
+    error = svglite_render(fb, svg, SVGLITE_FILL_RULE_AUTO, VG_LITE_BLEND_NONE, VG_LITE_HIGH, fonts);
     if (error != VG_LITE_SUCCESS) {
         printf("svglite_render() error: %d", error);
         return -1;